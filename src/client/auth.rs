@@ -0,0 +1,214 @@
+//! Choosing and applying an HTTP authentication scheme.
+
+use http_auth::{BasicClient, DigestClient, PasswordClient};
+use serde::Serialize;
+
+use super::Credentials;
+
+/// Which authentication schemes [`super::RtspConnection`] may use. `Digest` is
+/// always preferred when the server offers it.
+#[derive(Clone, Debug, Default)]
+pub struct AuthPolicy {
+    /// Never use `Basic`.
+    pub digest_only: bool,
+
+    /// Send `Basic` credentials with the first request rather than waiting to
+    /// be challenged. Some cameras require this.
+    pub preemptive_basic: bool,
+
+    /// Never send `Basic` credentials over a connection without TLS, where they
+    /// reveal the password to anyone watching.
+    pub basic_requires_tls: bool,
+}
+
+impl AuthPolicy {
+    fn allows_basic(&self, tls: bool) -> bool {
+        !self.digest_only && (tls || !self.basic_requires_tls)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthScheme {
+    Basic,
+    Digest,
+}
+
+impl AuthScheme {
+    pub(crate) fn of(client: &PasswordClient) -> Result<Self, String> {
+        match client {
+            PasswordClient::Basic(_) => Ok(AuthScheme::Basic),
+            PasswordClient::Digest(_) => Ok(AuthScheme::Digest),
+            _ => Err("unsupported authentication scheme".to_owned()),
+        }
+    }
+}
+
+/// Picks a client for the challenges in a `WWW-Authenticate` header according
+/// to `policy`.
+pub(crate) fn choose_client(
+    www_authenticate: &str,
+    policy: &AuthPolicy,
+    tls: bool,
+) -> Result<PasswordClient, String> {
+    let challenges = http_auth::parse_challenges(www_authenticate).map_err(|e| e.to_string())?;
+    let find = |scheme: &str| {
+        challenges
+            .iter()
+            .find(|c| c.scheme.eq_ignore_ascii_case(scheme))
+    };
+    if let Some(c) = find("Digest") {
+        return DigestClient::try_from(c)
+            .map(PasswordClient::Digest)
+            .map_err(|e| e.to_string());
+    }
+    if let Some(c) = find("Basic") {
+        if !policy.allows_basic(tls) {
+            return Err(format!(
+                "Server offers only Basic, which policy forbids{}",
+                if policy.digest_only {
+                    ""
+                } else {
+                    " without TLS"
+                }
+            ));
+        }
+        return BasicClient::try_from(c)
+            .map(PasswordClient::Basic)
+            .map_err(|e| e.to_string());
+    }
+    Err("No supported authentication scheme offered".to_owned())
+}
+
+/// Returns true if the header has a `Digest` challenge with `stale=true`,
+/// meaning the previous nonce expired but the credentials were accepted.
+pub(crate) fn is_stale(www_authenticate: &str) -> bool {
+    let challenges = match http_auth::parse_challenges(www_authenticate) {
+        Ok(c) => c,
+        Err(_) => return false,
+    };
+    challenges
+        .iter()
+        .filter(|c| c.scheme.eq_ignore_ascii_case("Digest"))
+        .flat_map(|c| c.params.iter())
+        .any(|(k, v)| {
+            k.eq_ignore_ascii_case("stale") && v.to_unescaped().eq_ignore_ascii_case("true")
+        })
+}
+
+/// Returns a preemptive `Basic` `Authorization` header value, if `policy` calls
+/// for one.
+pub(crate) fn preemptive_authorization(
+    creds: &Credentials,
+    policy: &AuthPolicy,
+    tls: bool,
+) -> Option<String> {
+    if !policy.preemptive_basic || !policy.allows_basic(tls) {
+        return None;
+    }
+    Some(format!(
+        "Basic {}",
+        base64::encode(format!("{}:{}", creds.username, creds.password))
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASIC: &str = r#"Basic realm="r""#;
+    const DIGEST: &str = r#"Digest realm="r", nonce="n", qop="auth""#;
+    const BOTH: &str = r#"Basic realm="r", Digest realm="r", nonce="n""#;
+
+    fn scheme(
+        www_authenticate: &str,
+        policy: &AuthPolicy,
+        tls: bool,
+    ) -> Result<AuthScheme, String> {
+        choose_client(www_authenticate, policy, tls).map(|c| AuthScheme::of(&c).unwrap())
+    }
+
+    #[test]
+    fn prefers_digest() {
+        let policy = AuthPolicy::default();
+        assert_eq!(scheme(BOTH, &policy, false), Ok(AuthScheme::Digest));
+        assert_eq!(scheme(DIGEST, &policy, false), Ok(AuthScheme::Digest));
+        assert_eq!(scheme(BASIC, &policy, false), Ok(AuthScheme::Basic));
+    }
+
+    #[test]
+    fn digest_only_refuses_basic() {
+        let policy = AuthPolicy {
+            digest_only: true,
+            ..Default::default()
+        };
+        assert_eq!(scheme(BOTH, &policy, false), Ok(AuthScheme::Digest));
+        assert_eq!(
+            scheme(BASIC, &policy, true),
+            Err("Server offers only Basic, which policy forbids".to_owned())
+        );
+    }
+
+    #[test]
+    fn basic_requires_tls() {
+        let policy = AuthPolicy {
+            basic_requires_tls: true,
+            ..Default::default()
+        };
+        assert_eq!(scheme(BASIC, &policy, true), Ok(AuthScheme::Basic));
+        assert_eq!(
+            scheme(BASIC, &policy, false),
+            Err("Server offers only Basic, which policy forbids without TLS".to_owned())
+        );
+    }
+
+    #[test]
+    fn unsupported_scheme() {
+        let policy = AuthPolicy::default();
+        assert_eq!(
+            scheme(r#"Bearer realm="r""#, &policy, false),
+            Err("No supported authentication scheme offered".to_owned())
+        );
+    }
+
+    #[test]
+    fn stale() {
+        assert!(is_stale(r#"Digest realm="r", nonce="n", stale=TRUE"#));
+        assert!(!is_stale(r#"Digest realm="r", nonce="n", stale=false"#));
+        assert!(!is_stale(DIGEST));
+        assert!(!is_stale(r#"Basic realm="r", stale=true"#));
+    }
+
+    #[test]
+    fn preemptive_basic() {
+        let creds = Credentials {
+            username: "Aladdin".to_owned(),
+            password: "open sesame".to_owned(),
+        };
+        let policy = AuthPolicy {
+            preemptive_basic: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            preemptive_authorization(&creds, &policy, false).as_deref(),
+            Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==")
+        );
+        assert_eq!(
+            preemptive_authorization(&creds, &AuthPolicy::default(), false),
+            None
+        );
+
+        // Policy that forbids Basic also forbids sending it preemptively.
+        let policy = AuthPolicy {
+            basic_requires_tls: true,
+            ..policy
+        };
+        assert_eq!(preemptive_authorization(&creds, &policy, false), None);
+        assert!(preemptive_authorization(&creds, &policy, true).is_some());
+        let policy = AuthPolicy {
+            digest_only: true,
+            ..policy
+        };
+        assert_eq!(preemptive_authorization(&creds, &policy, true), None);
+    }
+}
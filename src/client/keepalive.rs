@@ -0,0 +1,256 @@
+//! Keeping an RTSP session alive with heartbeat requests.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use rtsp_connection::{bail, wrap, ReceivedMessage, RtspMessageContext};
+use rtsp_types::{Message, StatusCode};
+use tokio::time::{Instant, Sleep};
+
+use super::{get_cseq, RtspConnection};
+use crate::error::{Error, ErrorInt};
+
+/// Heartbeat state for the session of an [`RtspConnection`].
+///
+/// A heartbeat is sent at half the session timeout: `GET_PARAMETER`, or
+/// `OPTIONS` if the server doesn't support that. Any other request also keeps
+/// the session alive, so postpones the next heartbeat. Heartbeats are sent
+/// whenever the connection waits for a message, both while awaiting the
+/// response to any request and while a [`super::play::Playing`] stream is
+/// polled, and a failed one is reported as the error of that wait.
+pub(super) struct Keepalive {
+    interval: Duration,
+    timer: Pin<Box<Sleep>>,
+    use_options: bool,
+
+    /// A heartbeat is due but hasn't yet been written.
+    due: bool,
+
+    /// A heartbeat has been written but not yet flushed.
+    flushing: bool,
+
+    /// The `CSeq` of the heartbeat awaiting a response.
+    cseq: Option<u32>,
+}
+
+impl Keepalive {
+    pub(super) fn new(session_timeout: Duration) -> Self {
+        let interval = session_timeout / 2;
+        Keepalive {
+            interval,
+            timer: Box::pin(tokio::time::sleep(interval)),
+            use_options: false,
+            due: false,
+            flushing: false,
+            cseq: None,
+        }
+    }
+
+    /// Restarts the interval, as after sending any request.
+    pub(super) fn postpone(&mut self) {
+        let next = Instant::now() + self.interval;
+        self.timer.as_mut().reset(next);
+    }
+
+    /// Records the status of the response to the outstanding heartbeat,
+    /// returning false if the heartbeat failed.
+    fn record_response(&mut self, status: StatusCode) -> bool {
+        self.cseq = None;
+        if status.is_success() {
+            return true;
+        }
+
+        // Method Not Allowed, Not Implemented or Option not supported: retry
+        // with OPTIONS right away.
+        if !self.use_options && matches!(u16::from(status), 405 | 501 | 551) {
+            self.use_options = true;
+            self.due = true;
+            return true;
+        }
+        false
+    }
+
+    fn method(&self) -> rtsp_types::Method {
+        if self.use_options {
+            rtsp_types::Method::Options
+        } else {
+            rtsp_types::Method::GetParameter
+        }
+    }
+}
+
+impl RtspConnection {
+    /// Reads the next message, meanwhile writing any heartbeat that falls due.
+    pub(super) fn poll_next_message(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<ReceivedMessage, Error>>> {
+        if let Err(e) = self.poll_keepalive(cx) {
+            return Poll::Ready(Some(Err(e)));
+        }
+        self.inner.poll_next_unpin(cx)
+    }
+
+    /// Writes a heartbeat when one is due, without waiting for its response,
+    /// which is checked by [`RtspConnection::handle_keepalive_response`].
+    fn poll_keepalive(&mut self, cx: &mut Context<'_>) -> Result<(), Error> {
+        // Taken so the request can be filled in while it's borrowed.
+        let mut keepalive = match self.keepalive.take() {
+            Some(k) => k,
+            None => return Ok(()),
+        };
+        let result = self.poll_keepalive_inner(&mut keepalive, cx);
+        self.keepalive = Some(keepalive);
+        result
+    }
+
+    fn poll_keepalive_inner(
+        &mut self,
+        keepalive: &mut Keepalive,
+        cx: &mut Context<'_>,
+    ) -> Result<(), Error> {
+        if keepalive.timer.as_mut().poll(cx).is_ready() {
+            keepalive.due = true;
+            keepalive.postpone();
+            let _ = keepalive.timer.as_mut().poll(cx);
+        }
+        if keepalive.due {
+            if let Poll::Ready(r) = self.inner.poll_ready_unpin(cx) {
+                r.map_err(|e| wrap!(e))?;
+                let mut req =
+                    rtsp_types::Request::builder(keepalive.method(), rtsp_types::Version::V1_0)
+                        .request_uri(Self::request_url(&self.url))
+                        .build(Bytes::new());
+                let cseq = self.fill_req(&mut req)?;
+                self.inner
+                    .start_send_unpin(Message::Request(req))
+                    .map_err(|e| wrap!(e))?;
+                keepalive.cseq = Some(cseq);
+                keepalive.due = false;
+                keepalive.flushing = true;
+            }
+        }
+        if keepalive.flushing {
+            if let Poll::Ready(r) = self.inner.poll_flush_unpin(cx) {
+                r.map_err(|e| wrap!(e))?;
+                keepalive.flushing = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks a response other than the one being waited for, failing if it
+    /// reports that a heartbeat failed.
+    pub(super) fn handle_keepalive_response(
+        &mut self,
+        response: &rtsp_types::Response<Bytes>,
+        msg_ctx: RtspMessageContext,
+    ) -> Result<(), Error> {
+        let keepalive = match &mut self.keepalive {
+            Some(k) => k,
+            None => return Ok(()),
+        };
+        let cseq = match get_cseq(response) {
+            Some(c) if Some(c) == keepalive.cseq => c,
+            _ => return Ok(()),
+        };
+        if keepalive.record_response(response.status()) {
+            return Ok(());
+        }
+        bail!(ErrorInt::RtspResponseError {
+            conn_ctx: *self.inner.ctx(),
+            msg_ctx,
+            method: keepalive.method(),
+            cseq,
+            status: response.status(),
+            description: "Keepalive failed".to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{connect, response, serve};
+    use super::*;
+    use crate::client::transport::{Session, TransportCandidate};
+
+    #[tokio::test]
+    async fn interval_is_half_the_session_timeout() {
+        let session = Session::parse("1234;timeout=10").unwrap();
+        assert_eq!(
+            Keepalive::new(session.timeout).interval,
+            Duration::from_secs(5)
+        );
+
+        // Without a timeout the server's is 60 seconds.
+        let session = Session::parse("1234").unwrap();
+        assert_eq!(
+            Keepalive::new(session.timeout).interval,
+            Duration::from_secs(30)
+        );
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_options() {
+        for status in [405, 501, 551] {
+            let status = StatusCode::from(status);
+            let mut k = Keepalive::new(Duration::from_secs(60));
+            assert_eq!(k.method(), rtsp_types::Method::GetParameter);
+            k.cseq = Some(3);
+            assert!(k.record_response(status));
+            assert_eq!(k.method(), rtsp_types::Method::Options);
+            assert!(k.due, "OPTIONS should be sent at once");
+            assert_eq!(k.cseq, None);
+
+            // OPTIONS failing too is a real failure.
+            k.due = false;
+            assert!(!k.record_response(status));
+        }
+    }
+
+    #[tokio::test]
+    async fn other_failures_are_reported() {
+        let mut k = Keepalive::new(Duration::from_secs(60));
+        assert!(k.record_response(StatusCode::Ok));
+        assert!(!k.record_response(StatusCode::SessionNotFound));
+        assert_eq!(k.method(), rtsp_types::Method::GetParameter);
+        assert!(!k.due);
+    }
+
+    #[tokio::test]
+    async fn sent_while_awaiting_a_response() {
+        // OPTIONS is only answered once the heartbeat, due after half a second
+        // and so sent while waiting for OPTIONS, arrives with the next CSeq.
+        let url = serve(|method, _, cseq| match method {
+            "SETUP" => response(
+                "200 OK",
+                cseq,
+                "Transport: RTP/AVP/TCP;unicast;interleaved=0-1\r\n\
+                 Session: 1234;timeout=1\r\n",
+            ),
+            "OPTIONS" => Vec::new(),
+            "GET_PARAMETER" => {
+                let options_cseq = (cseq.parse::<u32>().unwrap() - 1).to_string();
+                let mut r = response("200 OK", cseq, "");
+                r.extend(response("200 OK", &options_cseq, ""));
+                r
+            }
+            _ => response("200 OK", cseq, ""),
+        })
+        .await;
+        let mut conn = connect(&url, 0).await;
+        conn.setup(&url, 0, &[TransportCandidate::TcpInterleaved])
+            .await
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(5), conn.options())
+            .await
+            .expect("no heartbeat was sent")
+            .unwrap();
+    }
+}
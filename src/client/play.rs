@@ -0,0 +1,533 @@
+//! Receiving media interleaved on the RTSP connection after `PLAY`.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll, Waker},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use rtsp_connection::{bail, wrap, RtspMessageContext};
+use rtsp_types::Message;
+use serde::Serialize;
+use url::Url;
+
+use super::{
+    rtcp::{self, RtcpPacket, SenderReport},
+    rtp::RtpPacket,
+    stats::{SourceSnapshot, Statistics},
+    RtspConnection,
+};
+use crate::error::{Error, ErrorInt};
+
+/// What an interleaved channel carries.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelType {
+    Rtp,
+    Rtcp,
+}
+
+/// A packet received on an interleaved channel assigned by `SETUP`.
+#[derive(Debug)]
+pub struct InterleavedPacket {
+    pub ctx: RtspMessageContext,
+    pub channel_id: u8,
+
+    /// The index of the stream within the session description.
+    pub track: usize,
+    pub channel_type: ChannelType,
+    pub data: Bytes,
+}
+
+/// A playing session, yielding the packets of every track as they arrive.
+///
+/// Polling the stream sends the connection's session heartbeats, and yields an
+/// error if one fails. Other messages from the server are discarded. Use
+/// [`Playing::into_tracks`] for a separate stream per track and channel type.
+pub struct Playing {
+    conn: RtspConnection,
+
+    /// The latest sender report seen by [`Playing::rtcp`] for each track.
+    sender_reports: BTreeMap<usize, SenderReport>,
+
+    /// Statistics for packets seen by [`Playing::rtp`].
+    stats: Statistics,
+}
+
+/// The span over which [`Playing::stats`] computes bitrate.
+const BITRATE_WINDOW: Duration = Duration::from_secs(5);
+
+impl RtspConnection {
+    /// Sends `PLAY` for `url`, normally the session's aggregate control URL.
+    ///
+    /// Pass the connection to [`Playing::new`] to read the media interleaved on
+    /// it. On error the connection is kept, so the session can be torn down.
+    pub async fn play(&mut self, url: &Url) -> Result<(), Error> {
+        if self.session.is_none() {
+            bail!(ErrorInt::FailedPrecondition(
+                "PLAY requires a session established by SETUP".to_owned()
+            ));
+        }
+        let mut req =
+            rtsp_types::Request::builder(rtsp_types::Method::Play, rtsp_types::Version::V1_0)
+                .header(rtsp_types::headers::RANGE, "npt=0.000-")
+                .request_uri(Self::request_url(url))
+                .build(Bytes::new());
+        self.send_request(&mut req).await.map(|_| ())
+    }
+}
+
+impl Playing {
+    /// Reads the media of a session started by [`RtspConnection::play`].
+    pub fn new(conn: RtspConnection) -> Self {
+        Playing {
+            conn,
+            sender_reports: BTreeMap::new(),
+            stats: Statistics::new(BITRATE_WINDOW),
+        }
+    }
+
+    pub fn connection(&self) -> &RtspConnection {
+        &self.conn
+    }
+
+    /// Stops reading media, returning the connection so the session can be
+    /// torn down.
+    pub fn into_connection(self) -> RtspConnection {
+        self.conn
+    }
+
+    /// Parses `pkt`, which should come from an RTP channel, as an RTP packet,
+    /// and records it in [`Playing::stats`].
+    pub fn rtp(&mut self, pkt: &InterleavedPacket) -> Result<RtpPacket, Error> {
+        let conn_ctx = *self.conn.inner.ctx();
+        let err = |description| {
+            wrap!(ErrorInt::RtpPacketError {
+                conn_ctx,
+                msg_ctx: pkt.ctx,
+                channel_id: pkt.channel_id,
+                description,
+            })
+        };
+        if pkt.channel_type != ChannelType::Rtp {
+            return Err(err("packet is from an RTCP channel".to_owned()));
+        }
+        let rtp = RtpPacket::parse(pkt.data.clone()).map_err(err)?;
+        let clock_rate = self.conn.clock_rates.get(pkt.track).copied().flatten();
+        self.stats
+            .record(pkt.track, clock_rate, &rtp, pkt.ctx.received());
+        Ok(rtp)
+    }
+
+    /// Returns reception statistics for each SSRC seen so far.
+    pub fn stats(&self) -> Vec<SourceSnapshot> {
+        self.stats.snapshot(std::time::Instant::now())
+    }
+
+    /// Parses `pkt`, which should come from an RTCP channel, as a compound RTCP
+    /// packet, remembering any sender report for [`Playing::sender_report`].
+    pub fn rtcp(&mut self, pkt: &InterleavedPacket) -> Result<Vec<RtcpPacket>, Error> {
+        let conn_ctx = *self.conn.inner.ctx();
+        let err = |description| {
+            wrap!(ErrorInt::RtcpPacketError {
+                conn_ctx,
+                msg_ctx: pkt.ctx,
+                channel_id: pkt.channel_id,
+                description,
+            })
+        };
+        if pkt.channel_type != ChannelType::Rtcp {
+            return Err(err("packet is from an RTP channel".to_owned()));
+        }
+        let packets = rtcp::parse_compound(&pkt.data).map_err(err)?;
+        for p in &packets {
+            if let RtcpPacket::SenderReport(sr) = p {
+                self.sender_reports.insert(pkt.track, sr.clone());
+            }
+        }
+        Ok(packets)
+    }
+
+    /// Returns the latest sender report for `track`, which maps its RTP
+    /// timestamps to the sender's wall clock via [`SenderReport::wall_time`].
+    pub fn sender_report(&self, track: usize) -> Option<&SenderReport> {
+        self.sender_reports.get(&track)
+    }
+
+    /// Splits the session into a stream per track and channel type.
+    pub fn into_tracks(self) -> Tracks {
+        let queues = self
+            .conn
+            .channels
+            .values()
+            .map(|&key| (key, ChannelQueue::default()))
+            .collect();
+        Tracks(Arc::new(Mutex::new(Demux {
+            playing: self,
+            queues,
+            ended: false,
+        })))
+    }
+
+    fn packet(
+        &self,
+        ctx: RtspMessageContext,
+        data: rtsp_types::Data<Bytes>,
+    ) -> Result<InterleavedPacket, Error> {
+        let channel_id = data.channel_id();
+        match self.conn.channels.get(&channel_id) {
+            Some(&(track, channel_type)) => Ok(InterleavedPacket {
+                ctx,
+                channel_id,
+                track,
+                channel_type,
+                data: data.into_body(),
+            }),
+            None => bail!(ErrorInt::RtspUnassignedChannelError {
+                conn_ctx: *self.conn.inner.ctx(),
+                msg_ctx: ctx,
+                channel_id,
+            }),
+        }
+    }
+}
+
+impl Stream for Playing {
+    type Item = Result<InterleavedPacket, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if let Some((ctx, data)) = this.conn.pending_data.pop_front() {
+            return Poll::Ready(Some(this.packet(ctx, data)));
+        }
+        loop {
+            let msg = match ready!(this.conn.poll_next_message(cx)) {
+                None => return Poll::Ready(None),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                Some(Ok(msg)) => msg,
+            };
+            match msg.msg {
+                Message::Data(data) => return Poll::Ready(Some(this.packet(msg.ctx, data))),
+                Message::Response(r) => {
+                    if let Err(e) = this.conn.handle_keepalive_response(&r, msg.ctx) {
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                }
+                Message::Request(_) => {}
+            }
+        }
+    }
+}
+
+/// A [`Playing`] session read through a separate stream per track and channel
+/// type.
+///
+/// Whichever stream is polled reads from the connection, queueing packets for
+/// the others, so each stream taken should be polled or dropped. Packets of a
+/// channel with no stream are discarded. Errors, such as a failed heartbeat,
+/// are yielded to whichever stream was polled.
+pub struct Tracks(Arc<Mutex<Demux>>);
+
+struct Demux {
+    playing: Playing,
+    queues: BTreeMap<(usize, ChannelType), ChannelQueue>,
+
+    /// The server closed the connection, so no more packets will arrive.
+    ended: bool,
+}
+
+#[derive(Default)]
+struct ChannelQueue {
+    /// Whether a [`TrackStream`] for the channel exists.
+    taken: bool,
+    packets: VecDeque<InterleavedPacket>,
+
+    /// The waker of the stream, if it's waiting for a packet.
+    waker: Option<Waker>,
+}
+
+impl Demux {
+    /// Wakes every stream waiting for a packet, so one of them polls the
+    /// connection.
+    fn wake_all(&mut self) {
+        for q in self.queues.values_mut() {
+            if let Some(w) = q.waker.take() {
+                w.wake();
+            }
+        }
+    }
+}
+
+impl Tracks {
+    /// Returns the stream of `channel_type` packets of `track`, or `None` if
+    /// `SETUP` assigned no such channel or its stream was already taken.
+    pub fn stream(&self, track: usize, channel_type: ChannelType) -> Option<TrackStream> {
+        let mut demux = self.0.lock().unwrap();
+        let queue = demux.queues.get_mut(&(track, channel_type))?;
+        if queue.taken {
+            return None;
+        }
+        queue.taken = true;
+        Some(TrackStream {
+            demux: self.0.clone(),
+            track,
+            channel_type,
+        })
+    }
+
+    /// As [`Playing::rtp`].
+    pub fn rtp(&self, pkt: &InterleavedPacket) -> Result<RtpPacket, Error> {
+        self.0.lock().unwrap().playing.rtp(pkt)
+    }
+
+    /// As [`Playing::rtcp`].
+    pub fn rtcp(&self, pkt: &InterleavedPacket) -> Result<Vec<RtcpPacket>, Error> {
+        self.0.lock().unwrap().playing.rtcp(pkt)
+    }
+
+    /// As [`Playing::stats`].
+    pub fn stats(&self) -> Vec<SourceSnapshot> {
+        self.0.lock().unwrap().playing.stats()
+    }
+
+    /// As [`Playing::sender_report`].
+    pub fn sender_report(&self, track: usize) -> Option<SenderReport> {
+        self.0.lock().unwrap().playing.sender_report(track).cloned()
+    }
+
+    /// Stops reading media, returning the connection so the session can be
+    /// torn down, or `self` if some stream hasn't been dropped.
+    pub fn into_connection(self) -> Result<RtspConnection, Self> {
+        match Arc::try_unwrap(self.0) {
+            Ok(demux) => Ok(demux.into_inner().unwrap().playing.into_connection()),
+            Err(demux) => Err(Tracks(demux)),
+        }
+    }
+}
+
+/// The packets of one channel of a track, from [`Tracks::stream`].
+pub struct TrackStream {
+    demux: Arc<Mutex<Demux>>,
+    track: usize,
+    channel_type: ChannelType,
+}
+
+impl TrackStream {
+    pub fn track(&self) -> usize {
+        self.track
+    }
+
+    pub fn channel_type(&self) -> ChannelType {
+        self.channel_type
+    }
+}
+
+impl Stream for TrackStream {
+    type Item = Result<InterleavedPacket, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let key = (self.track, self.channel_type);
+        let mut guard = self.demux.lock().unwrap();
+        let demux = &mut *guard;
+        loop {
+            let queue = demux.queues.get_mut(&key).unwrap();
+            if let Some(pkt) = queue.packets.pop_front() {
+                return Poll::Ready(Some(Ok(pkt)));
+            }
+            if demux.ended {
+                return Poll::Ready(None);
+            }
+            let pkt = match demux.playing.poll_next_unpin(cx) {
+                Poll::Pending => {
+                    queue.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                Poll::Ready(None) => {
+                    demux.ended = true;
+                    demux.wake_all();
+                    return Poll::Ready(None);
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(Some(Ok(pkt))) => pkt,
+            };
+            if (pkt.track, pkt.channel_type) == key {
+                return Poll::Ready(Some(Ok(pkt)));
+            }
+            if let Some(q) = demux.queues.get_mut(&(pkt.track, pkt.channel_type)) {
+                if q.taken {
+                    q.packets.push_back(pkt);
+                    if let Some(w) = q.waker.take() {
+                        w.wake();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for TrackStream {
+    fn drop(&mut self) {
+        let mut demux = match self.demux.lock() {
+            Ok(d) => d,
+            Err(_) => return,
+        };
+        if let Some(q) = demux.queues.get_mut(&(self.track, self.channel_type)) {
+            *q = ChannelQueue::default();
+        }
+
+        // The connection may only wake this stream, so another must poll it.
+        demux.wake_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{connect, response, serve};
+    use super::*;
+    use crate::client::transport::TransportCandidate;
+
+    const SETUP_HEADERS: &str = "Transport: RTP/AVP/TCP;unicast;interleaved=0-1\r\n\
+                                 Session: 1234;timeout=1\r\n";
+
+    /// Sets up and plays the session at `url`, whose heartbeats are due every
+    /// half second.
+    async fn play(url: &Url) -> Playing {
+        let mut conn = connect(url, 0).await;
+        conn.setup(url, 0, &[TransportCandidate::TcpInterleaved])
+            .await
+            .unwrap();
+        conn.play(url).await.unwrap();
+        Playing::new(conn)
+    }
+
+    #[tokio::test]
+    async fn keepalive_falls_back_to_options() {
+        let url = serve(|method, _, cseq| match method {
+            "SETUP" => response("200 OK", cseq, SETUP_HEADERS),
+            "GET_PARAMETER" => response("501 Not Implemented", cseq, ""),
+            "OPTIONS" => {
+                // Show that the heartbeat arrived by sending a packet after it.
+                let mut r = response("200 OK", cseq, "");
+                r.extend_from_slice(b"$\x00\x00\x07options");
+                r
+            }
+            _ => response("200 OK", cseq, ""),
+        })
+        .await;
+        let mut playing = play(&url).await;
+        let pkt = playing.next().await.unwrap().unwrap();
+        assert_eq!((pkt.track, pkt.channel_type), (0, ChannelType::Rtp));
+        assert_eq!(&pkt.data[..], b"options");
+    }
+
+    #[tokio::test]
+    async fn keepalive_failure() {
+        let url = serve(|method, _, cseq| match method {
+            "SETUP" => response("200 OK", cseq, SETUP_HEADERS),
+            "GET_PARAMETER" => response("454 Session Not Found", cseq, ""),
+            _ => response("200 OK", cseq, ""),
+        })
+        .await;
+        let mut playing = play(&url).await;
+        let e = playing.next().await.unwrap().unwrap_err();
+        assert!(matches!(
+            &*e.0,
+            ErrorInt::RtspResponseError { method: rtsp_types::Method::GetParameter, status, .. }
+                if u16::from(*status) == 454
+        ));
+    }
+
+    #[tokio::test]
+    async fn unassigned_channel() {
+        let url = serve(|method, _, cseq| match method {
+            "SETUP" => response("200 OK", cseq, SETUP_HEADERS),
+            "PLAY" => {
+                let mut r = response("200 OK", cseq, "");
+                r.extend_from_slice(b"$\x02\x00\x01x");
+                r
+            }
+            _ => response("200 OK", cseq, ""),
+        })
+        .await;
+        let mut playing = play(&url).await;
+        let e = playing.next().await.unwrap().unwrap_err();
+        assert!(matches!(
+            &*e.0,
+            ErrorInt::RtspUnassignedChannelError { channel_id: 2, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn data_before_play_response() {
+        let url = serve(|method, _, cseq| match method {
+            "SETUP" => response("200 OK", cseq, SETUP_HEADERS),
+            "PLAY" => {
+                let mut r = b"$\x00\x00\x05early".to_vec();
+                r.extend(response("200 OK", cseq, ""));
+                r
+            }
+            _ => response("200 OK", cseq, ""),
+        })
+        .await;
+        let mut playing = play(&url).await;
+        let pkt = tokio::time::timeout(Duration::from_secs(5), playing.next())
+            .await
+            .expect("packet was dropped")
+            .unwrap()
+            .unwrap();
+        assert_eq!((pkt.track, pkt.channel_type), (0, ChannelType::Rtp));
+        assert_eq!(&pkt.data[..], b"early");
+    }
+
+    #[tokio::test]
+    async fn per_track_streams() {
+        let url = serve(|method, path, cseq| match (method, path) {
+            ("SETUP", "/0") => response("200 OK", cseq, SETUP_HEADERS),
+            ("SETUP", "/1") => response(
+                "200 OK",
+                cseq,
+                "Transport: RTP/AVP/TCP;unicast;interleaved=2-3\r\nSession: 1234\r\n",
+            ),
+            ("PLAY", _) => {
+                let mut r = response("200 OK", cseq, "");
+                r.extend_from_slice(b"$\x02\x00\x02b1"); // track 1 RTP
+                r.extend_from_slice(b"$\x03\x00\x02bc"); // track 1 RTCP, not taken
+                r.extend_from_slice(b"$\x01\x00\x02ac"); // track 0 RTCP
+                r.extend_from_slice(b"$\x00\x00\x02a1"); // track 0 RTP
+                r.extend_from_slice(b"$\x02\x00\x02b2");
+                r
+            }
+            _ => response("200 OK", cseq, ""),
+        })
+        .await;
+        let mut conn = connect(&url, 0).await;
+        for track in 0..2 {
+            let control = url.join(&track.to_string()).unwrap();
+            conn.setup(&control, track, &[TransportCandidate::TcpInterleaved])
+                .await
+                .unwrap();
+        }
+        conn.play(&url).await.unwrap();
+        let tracks = Playing::new(conn).into_tracks();
+        let mut rtp0 = tracks.stream(0, ChannelType::Rtp).unwrap();
+        let mut rtcp0 = tracks.stream(0, ChannelType::Rtcp).unwrap();
+        let mut rtp1 = tracks.stream(1, ChannelType::Rtp).unwrap();
+        // Already taken, and no such track.
+        assert!(tracks.stream(0, ChannelType::Rtp).is_none());
+        assert!(tracks.stream(2, ChannelType::Rtp).is_none());
+
+        let data = |pkt: Option<Result<InterleavedPacket, Error>>| pkt.unwrap().unwrap().data;
+        assert_eq!(&data(rtp0.next().await)[..], b"a1");
+        assert_eq!(&data(rtp1.next().await)[..], b"b1");
+        assert_eq!(&data(rtcp0.next().await)[..], b"ac");
+        assert_eq!(&data(rtp1.next().await)[..], b"b2");
+
+        let tracks = tracks.into_connection().err().unwrap();
+        drop((rtp0, rtcp0, rtp1));
+        tracks.into_connection().ok().unwrap();
+    }
+}
@@ -0,0 +1,480 @@
+//! RTCP compound packets, as in
+//! [RFC 3550 section 6](https://datatracker.ietf.org/doc/html/rfc3550#section-6).
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use serde::Serialize;
+
+/// Seconds from the NTP epoch (1900) to the Unix epoch (1970).
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// A 64-bit NTP timestamp: seconds since 1900 in the high word, fraction in the low.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct NtpTimestamp(pub u64);
+
+impl NtpTimestamp {
+    /// Converts to a `SystemTime`, or `None` for times before the Unix epoch.
+    pub fn to_system_time(self) -> Option<SystemTime> {
+        let secs = (self.0 >> 32).checked_sub(NTP_UNIX_OFFSET)?;
+        let nanos = ((self.0 & 0xffff_ffff) * 1_000_000_000) >> 32;
+        Some(UNIX_EPOCH + Duration::new(secs, nanos as u32))
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RtcpPacket {
+    SenderReport(SenderReport),
+    ReceiverReport(ReceiverReport),
+    SourceDescription { chunks: Vec<SdesChunk> },
+    Bye(Bye),
+
+    /// A packet of another type, such as `APP`, left unparsed.
+    Other {
+        packet_type: u8,
+        #[serde(skip)]
+        data: Bytes,
+    },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SenderReport {
+    pub ssrc: u32,
+    pub ntp_timestamp: NtpTimestamp,
+
+    /// The RTP timestamp corresponding to `ntp_timestamp`.
+    pub rtp_timestamp: u32,
+    pub packet_count: u32,
+    pub octet_count: u32,
+    pub reports: Vec<ReportBlock>,
+}
+
+impl SenderReport {
+    /// Returns the sender's wall-clock time for an RTP timestamp of the same
+    /// stream, using this report's NTP/RTP timestamp pair.
+    ///
+    /// `rtp_timestamp` should be within about half the 32-bit range of the
+    /// report's timestamp, as it is for packets near the report. Returns
+    /// `None` if `clock_rate` is zero.
+    pub fn wall_time(&self, rtp_timestamp: u32, clock_rate: u32) -> Option<SystemTime> {
+        if clock_rate == 0 {
+            return None;
+        }
+        let base = self.ntp_timestamp.to_system_time()?;
+        let delta = rtp_timestamp.wrapping_sub(self.rtp_timestamp) as i32;
+        let offset =
+            Duration::from_secs_f64(f64::from(delta.unsigned_abs()) / f64::from(clock_rate));
+        if delta >= 0 {
+            base.checked_add(offset)
+        } else {
+            base.checked_sub(offset)
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ReceiverReport {
+    pub ssrc: u32,
+    pub reports: Vec<ReportBlock>,
+}
+
+/// Reception statistics for one source.
+#[derive(Clone, Debug, Serialize)]
+pub struct ReportBlock {
+    pub ssrc: u32,
+    pub fraction_lost: u8,
+    pub cumulative_lost: i32,
+    pub extended_highest_sequence: u32,
+    pub jitter: u32,
+    pub last_sr: u32,
+    pub delay_since_last_sr: u32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SdesChunk {
+    pub ssrc: u32,
+    pub cname: Option<String>,
+
+    /// Items other than `CNAME`, as `(type, text)`.
+    pub items: Vec<(u8, String)>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Bye {
+    pub ssrcs: Vec<u32>,
+    pub reason: Option<String>,
+}
+
+/// Parses a compound RTCP packet, which must begin with a sender or receiver report.
+pub fn parse_compound(data: &Bytes) -> Result<Vec<RtcpPacket>, String> {
+    let mut packets = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let header = data
+            .get(pos..pos + 4)
+            .ok_or_else(|| format!("truncated header at {}", pos))?;
+        let version = header[0] >> 6;
+        if version != 2 {
+            return Err(format!("unsupported version {} at {}", version, pos));
+        }
+        let count = usize::from(header[0] & 0x1f);
+        let packet_type = header[1];
+        let len = 4 * (usize::from(u16::from_be_bytes([header[2], header[3]])) + 1);
+        let end = pos + len;
+        if end > data.len() {
+            return Err(format!(
+                "{}-byte packet at {} exceeds {}-byte compound",
+                len,
+                pos,
+                data.len()
+            ));
+        }
+        let mut body_end = end;
+        if header[0] & 0x20 != 0 {
+            if end != data.len() {
+                return Err(format!("padding on non-final packet at {}", pos));
+            }
+            let padding = usize::from(data[end - 1]);
+            if padding == 0 || pos + 4 + padding > end {
+                return Err(format!("bad padding length {} at {}", padding, pos));
+            }
+            body_end -= padding;
+        }
+        if packets.is_empty() && packet_type != 200 && packet_type != 201 {
+            return Err(format!(
+                "compound packet starts with type {}, not SR or RR",
+                packet_type
+            ));
+        }
+        let body = &data[pos + 4..body_end];
+        let packet = match packet_type {
+            200 => RtcpPacket::SenderReport(parse_sr(body, count)?),
+            201 => RtcpPacket::ReceiverReport(parse_rr(body, count)?),
+            202 => RtcpPacket::SourceDescription {
+                chunks: parse_sdes(body, count)?,
+            },
+            203 => RtcpPacket::Bye(parse_bye(body, count)?),
+            _ => RtcpPacket::Other {
+                packet_type,
+                data: data.slice(pos..end),
+            },
+        };
+        packets.push(packet);
+        pos = end;
+    }
+    if packets.is_empty() {
+        return Err("empty compound packet".to_owned());
+    }
+    Ok(packets)
+}
+
+fn read_u32(b: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes([b[pos], b[pos + 1], b[pos + 2], b[pos + 3]])
+}
+
+fn parse_sr(body: &[u8], count: usize) -> Result<SenderReport, String> {
+    if body.len() < 24 + 24 * count {
+        return Err(format!(
+            "{}-byte SR too short for {} report blocks",
+            body.len(),
+            count
+        ));
+    }
+    Ok(SenderReport {
+        ssrc: read_u32(body, 0),
+        ntp_timestamp: NtpTimestamp(
+            u64::from(read_u32(body, 4)) << 32 | u64::from(read_u32(body, 8)),
+        ),
+        rtp_timestamp: read_u32(body, 12),
+        packet_count: read_u32(body, 16),
+        octet_count: read_u32(body, 20),
+        reports: parse_report_blocks(&body[24..], count),
+    })
+}
+
+fn parse_rr(body: &[u8], count: usize) -> Result<ReceiverReport, String> {
+    if body.len() < 4 + 24 * count {
+        return Err(format!(
+            "{}-byte RR too short for {} report blocks",
+            body.len(),
+            count
+        ));
+    }
+    Ok(ReceiverReport {
+        ssrc: read_u32(body, 0),
+        reports: parse_report_blocks(&body[4..], count),
+    })
+}
+
+/// Parses `count` report blocks; the caller checks the length.
+fn parse_report_blocks(b: &[u8], count: usize) -> Vec<ReportBlock> {
+    b.chunks_exact(24)
+        .take(count)
+        .map(|r| {
+            // cumulative_lost is a signed 24-bit value.
+            let cumulative_lost = (read_u32(r, 4) << 8) as i32 >> 8;
+            ReportBlock {
+                ssrc: read_u32(r, 0),
+                fraction_lost: r[4],
+                cumulative_lost,
+                extended_highest_sequence: read_u32(r, 8),
+                jitter: read_u32(r, 12),
+                last_sr: read_u32(r, 16),
+                delay_since_last_sr: read_u32(r, 20),
+            }
+        })
+        .collect()
+}
+
+fn parse_sdes(body: &[u8], count: usize) -> Result<Vec<SdesChunk>, String> {
+    let mut chunks = Vec::with_capacity(count);
+    let mut pos = 0;
+    for _ in 0..count {
+        if body.len() < pos + 4 {
+            return Err(format!("truncated SDES chunk at {}", pos));
+        }
+        let mut chunk = SdesChunk {
+            ssrc: read_u32(body, pos),
+            cname: None,
+            items: Vec::new(),
+        };
+        pos += 4;
+        loop {
+            let item_type = *body
+                .get(pos)
+                .ok_or_else(|| format!("unterminated SDES chunk at {}", pos))?;
+            if item_type == 0 {
+                // The item list ends with a null byte, then pads to a word boundary.
+                pos = (pos + 4) & !3;
+                break;
+            }
+            let len = usize::from(
+                *body
+                    .get(pos + 1)
+                    .ok_or_else(|| format!("truncated SDES item at {}", pos))?,
+            );
+            let text = body
+                .get(pos + 2..pos + 2 + len)
+                .ok_or_else(|| format!("truncated SDES item at {}", pos))?;
+            let text = String::from_utf8_lossy(text).into_owned();
+            if item_type == 1 {
+                chunk.cname = Some(text);
+            } else {
+                chunk.items.push((item_type, text));
+            }
+            pos += 2 + len;
+        }
+        chunks.push(chunk);
+    }
+    Ok(chunks)
+}
+
+fn parse_bye(body: &[u8], count: usize) -> Result<Bye, String> {
+    if body.len() < 4 * count {
+        return Err(format!(
+            "{}-byte BYE too short for {} SSRCs",
+            body.len(),
+            count
+        ));
+    }
+    let ssrcs = (0..count).map(|i| read_u32(body, 4 * i)).collect();
+    let rest = &body[4 * count..];
+    let reason = match rest.first() {
+        Some(&len) => {
+            let text = rest
+                .get(1..1 + usize::from(len))
+                .ok_or_else(|| "truncated BYE reason".to_owned())?;
+            Some(String::from_utf8_lossy(text).into_owned())
+        }
+        None => None,
+    };
+    Ok(Bye { ssrcs, reason })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds one packet with the given first byte, type and body, which must
+    /// be a whole number of words including any padding.
+    fn packet(first: u8, packet_type: u8, body: &[u8]) -> Vec<u8> {
+        assert_eq!(body.len() % 4, 0);
+        let words = (body.len() / 4) as u16;
+        let mut p = vec![first, packet_type];
+        p.extend_from_slice(&words.to_be_bytes());
+        p.extend_from_slice(body);
+        p
+    }
+
+    fn report_block(ssrc: u32, cumulative_lost: [u8; 3]) -> Vec<u8> {
+        let mut b = ssrc.to_be_bytes().to_vec();
+        b.push(64); // fraction lost
+        b.extend_from_slice(&cumulative_lost);
+        b.extend_from_slice(&70_000u32.to_be_bytes()); // extended highest
+        b.extend_from_slice(&12u32.to_be_bytes()); // jitter
+        b.extend_from_slice(&0x1234_5678u32.to_be_bytes()); // last SR
+        b.extend_from_slice(&65536u32.to_be_bytes()); // delay since last SR
+        b
+    }
+
+    fn sr_body(ntp: u64, rtp_timestamp: u32) -> Vec<u8> {
+        let mut b = 1u32.to_be_bytes().to_vec();
+        b.extend_from_slice(&ntp.to_be_bytes());
+        b.extend_from_slice(&rtp_timestamp.to_be_bytes());
+        b.extend_from_slice(&10u32.to_be_bytes()); // packet count
+        b.extend_from_slice(&1000u32.to_be_bytes()); // octet count
+        b
+    }
+
+    fn parse(data: Vec<u8>) -> Result<Vec<RtcpPacket>, String> {
+        parse_compound(&Bytes::from(data))
+    }
+
+    fn sender_report(ntp: u64, rtp_timestamp: u32) -> SenderReport {
+        match &parse(packet(0x80, 200, &sr_body(ntp, rtp_timestamp))).unwrap()[..] {
+            [RtcpPacket::SenderReport(sr)] => sr.clone(),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn sr() {
+        let mut body = sr_body(0x0102_0304_0506_0708, 90_000);
+        body.extend_from_slice(&report_block(2, [0, 0, 5]));
+        let packets = parse(packet(0x81, 200, &body)).unwrap();
+        let sr = match &packets[..] {
+            [RtcpPacket::SenderReport(sr)] => sr,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(sr.ssrc, 1);
+        assert_eq!(sr.ntp_timestamp, NtpTimestamp(0x0102_0304_0506_0708));
+        assert_eq!(sr.rtp_timestamp, 90_000);
+        assert_eq!((sr.packet_count, sr.octet_count), (10, 1000));
+        assert_eq!(sr.reports.len(), 1);
+        let r = &sr.reports[0];
+        assert_eq!((r.ssrc, r.fraction_lost, r.cumulative_lost), (2, 64, 5));
+        assert_eq!(r.extended_highest_sequence, 70_000);
+        assert_eq!(r.jitter, 12);
+        assert_eq!((r.last_sr, r.delay_since_last_sr), (0x1234_5678, 65536));
+
+        // The report count must fit in the packet.
+        assert!(parse(packet(0x82, 200, &body))
+            .unwrap_err()
+            .contains("too short for 2 report blocks"));
+    }
+
+    #[test]
+    fn rr_with_report_blocks() {
+        let mut body = 7u32.to_be_bytes().to_vec();
+        body.extend_from_slice(&report_block(2, [0, 0, 1]));
+        body.extend_from_slice(&report_block(3, [0xff, 0xff, 0xfe]));
+        let packets = parse(packet(0x82, 201, &body)).unwrap();
+        let rr = match &packets[..] {
+            [RtcpPacket::ReceiverReport(rr)] => rr,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(rr.ssrc, 7);
+        let lost: Vec<_> = rr
+            .reports
+            .iter()
+            .map(|r| (r.ssrc, r.cumulative_lost))
+            .collect();
+        assert_eq!(lost, [(2, 1), (3, -2)]);
+    }
+
+    #[test]
+    fn sdes_with_padding() {
+        let mut data = packet(0x80, 201, &7u32.to_be_bytes());
+
+        // One chunk: CNAME "cam", a note "hi", the null terminator and one
+        // byte to reach a word boundary, then 4 bytes of packet padding.
+        let mut body = 9u32.to_be_bytes().to_vec();
+        body.extend_from_slice(&[1, 3, b'c', b'a', b'm', 7, 2, b'h', b'i', 0, 0, 0]);
+        body.extend_from_slice(&[0, 0, 0, 4]);
+        data.extend(packet(0xa1, 202, &body));
+
+        let packets = parse(data).unwrap();
+        let chunks = match &packets[..] {
+            [RtcpPacket::ReceiverReport(_), RtcpPacket::SourceDescription { chunks }] => chunks,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].ssrc, 9);
+        assert_eq!(chunks[0].cname.as_deref(), Some("cam"));
+        assert_eq!(chunks[0].items, [(7, "hi".to_owned())]);
+    }
+
+    #[test]
+    fn bye() {
+        let mut data = packet(0x80, 201, &7u32.to_be_bytes());
+        let mut body = 7u32.to_be_bytes().to_vec();
+        body.extend_from_slice(&8u32.to_be_bytes());
+        body.extend_from_slice(&[4, b'g', b'o', b'n', b'e', 0, 0, 0]);
+        data.extend(packet(0x82, 203, &body));
+        let packets = parse(data).unwrap();
+        let bye = match &packets[..] {
+            [RtcpPacket::ReceiverReport(_), RtcpPacket::Bye(bye)] => bye,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(bye.ssrcs, [7, 8]);
+        assert_eq!(bye.reason.as_deref(), Some("gone"));
+    }
+
+    #[test]
+    fn bad_padding() {
+        let rr = packet(0x80, 201, &7u32.to_be_bytes());
+
+        // A padding length of zero.
+        let mut data = rr.clone();
+        data.extend(packet(0xa0, 203, &[0, 0, 0, 0]));
+        assert!(parse(data).unwrap_err().contains("bad padding length 0"));
+
+        // Padding longer than the body.
+        let mut data = rr.clone();
+        data.extend(packet(0xa0, 203, &[0, 0, 0, 8]));
+        assert!(parse(data).unwrap_err().contains("bad padding length 8"));
+
+        // Padding on a packet other than the last.
+        let mut data = packet(0xa0, 201, &[0, 0, 0, 4]);
+        data.extend(rr);
+        assert!(parse(data).unwrap_err().contains("padding on non-final"));
+    }
+
+    #[test]
+    fn bad_length() {
+        let mut data = packet(0x80, 201, &7u32.to_be_bytes());
+        data[3] = 2; // claims 12 bytes, has 8
+        assert!(parse(data.clone()).unwrap_err().contains("exceeds 8-byte"));
+
+        data.truncate(2);
+        assert!(parse(data).unwrap_err().contains("truncated header at 0"));
+        assert!(parse(Vec::new()).unwrap_err().contains("empty"));
+    }
+
+    #[test]
+    fn first_packet_not_a_report() {
+        let data = packet(0x80, 203, &7u32.to_be_bytes());
+        assert!(parse(data)
+            .unwrap_err()
+            .contains("starts with type 203, not SR or RR"));
+    }
+
+    #[test]
+    fn wall_time() {
+        // 1000.5 seconds after the Unix epoch, at RTP timestamp 90,000.
+        let sr = sender_report((NTP_UNIX_OFFSET + 1000) << 32 | 0x8000_0000, 90_000);
+        let at = |ms| Some(UNIX_EPOCH + Duration::from_millis(ms));
+        assert_eq!(sr.wall_time(90_000, 90_000), at(1_000_500));
+        assert_eq!(sr.wall_time(135_000, 90_000), at(1_001_000));
+        assert_eq!(sr.wall_time(0, 90_000), at(999_500));
+
+        // Timestamps wrap around.
+        let sr = sender_report((NTP_UNIX_OFFSET + 1000) << 32, u32::MAX - 44_999);
+        assert_eq!(sr.wall_time(45_000, 90_000), at(1_001_000));
+
+        // A zero clock rate or a time before 1970 has no answer.
+        assert_eq!(sr.wall_time(45_000, 0), None);
+        assert_eq!(sender_report(1 << 32, 0).wall_time(0, 90_000), None);
+    }
+}
@@ -0,0 +1,238 @@
+//! RTP packets, as in [RFC 3550 section 5.1](https://datatracker.ietf.org/doc/html/rfc3550#section-5.1).
+
+use std::ops::Range;
+
+use bytes::Bytes;
+
+const FIXED_HEADER_LEN: usize = 12;
+
+/// A validated RTP packet, borrowing its fields from the received bytes.
+#[derive(Clone, Debug)]
+pub struct RtpPacket {
+    data: Bytes,
+    payload: Range<usize>,
+}
+
+impl RtpPacket {
+    /// Validates the header of `data`, which must hold exactly one packet.
+    pub fn parse(data: Bytes) -> Result<Self, String> {
+        if data.len() < FIXED_HEADER_LEN {
+            return Err(format!(
+                "{}-byte packet is shorter than the {}-byte fixed header",
+                data.len(),
+                FIXED_HEADER_LEN
+            ));
+        }
+        let version = data[0] >> 6;
+        if version != 2 {
+            return Err(format!("unsupported version {}", version));
+        }
+        let csrc_count = usize::from(data[0] & 0x0f);
+        let mut start = FIXED_HEADER_LEN + 4 * csrc_count;
+        if data[0] & 0x10 != 0 {
+            // The extension header: 16-bit profile, 16-bit length in words.
+            if data.len() < start + 4 {
+                return Err(format!(
+                    "{}-byte packet too short for extension header at {}",
+                    data.len(),
+                    start
+                ));
+            }
+            let words = usize::from(u16::from_be_bytes([data[start + 2], data[start + 3]]));
+            start += 4 + 4 * words;
+        }
+        if data.len() < start {
+            return Err(format!(
+                "{}-byte packet too short for {}-byte header",
+                data.len(),
+                start
+            ));
+        }
+        let mut end = data.len();
+        if data[0] & 0x20 != 0 {
+            let padding = usize::from(data[end - 1]);
+            if padding == 0 || start + padding > end {
+                return Err(format!(
+                    "bad padding length {} with {} bytes after the header",
+                    padding,
+                    end - start
+                ));
+            }
+            end -= padding;
+        }
+        Ok(RtpPacket {
+            data,
+            payload: start..end,
+        })
+    }
+
+    pub fn padding(&self) -> bool {
+        self.data[0] & 0x20 != 0
+    }
+
+    pub fn extension(&self) -> bool {
+        self.data[0] & 0x10 != 0
+    }
+
+    pub fn csrc_count(&self) -> usize {
+        usize::from(self.data[0] & 0x0f)
+    }
+
+    pub fn mark(&self) -> bool {
+        self.data[1] & 0x80 != 0
+    }
+
+    pub fn payload_type(&self) -> u8 {
+        self.data[1] & 0x7f
+    }
+
+    pub fn sequence_number(&self) -> u16 {
+        u16::from_be_bytes([self.data[2], self.data[3]])
+    }
+
+    pub fn timestamp(&self) -> u32 {
+        u32::from_be_bytes([self.data[4], self.data[5], self.data[6], self.data[7]])
+    }
+
+    pub fn ssrc(&self) -> u32 {
+        u32::from_be_bytes([self.data[8], self.data[9], self.data[10], self.data[11]])
+    }
+
+    pub fn csrcs(&self) -> impl Iterator<Item = u32> + '_ {
+        self.data[FIXED_HEADER_LEN..FIXED_HEADER_LEN + 4 * self.csrc_count()]
+            .chunks_exact(4)
+            .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+    }
+
+    /// Returns the header extension's profile-defined identifier and data, if present.
+    pub fn extension_data(&self) -> Option<(u16, &[u8])> {
+        if !self.extension() {
+            return None;
+        }
+        let start = FIXED_HEADER_LEN + 4 * self.csrc_count();
+        let profile = u16::from_be_bytes([self.data[start], self.data[start + 1]]);
+        Some((profile, &self.data[start + 4..self.payload.start]))
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.data[self.payload.clone()]
+    }
+
+    /// Returns the payload as a `Bytes` sharing the packet's buffer.
+    pub fn payload_bytes(&self) -> Bytes {
+        self.data.slice(self.payload.clone())
+    }
+
+    /// Returns the whole packet as received.
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fixed header with the given first byte, marker set, payload type 96,
+    /// sequence number 0x0102, timestamp 0x03040506 and SSRC 0x0708090a.
+    fn header(first: u8) -> Vec<u8> {
+        vec![first, 0xe0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10]
+    }
+
+    fn parse(data: Vec<u8>) -> Result<RtpPacket, String> {
+        RtpPacket::parse(Bytes::from(data))
+    }
+
+    #[test]
+    fn csrcs_and_extension() {
+        // Padding, an extension and two CSRCs.
+        let mut data = header(0xb2);
+        data.extend_from_slice(&[0, 0, 0, 11, 0, 0, 0, 12]);
+        data.extend_from_slice(&[0xbe, 0xde, 0, 1, 0x10, 0xaa, 0, 0]);
+        data.extend_from_slice(b"payload");
+        data.extend_from_slice(&[0, 0, 3]);
+        let pkt = parse(data).unwrap();
+        assert!(pkt.padding() && pkt.extension() && pkt.mark());
+        assert_eq!(pkt.payload_type(), 96);
+        assert_eq!(pkt.sequence_number(), 0x0102);
+        assert_eq!(pkt.timestamp(), 0x0304_0506);
+        assert_eq!(pkt.ssrc(), 0x0708_090a);
+        assert_eq!(pkt.csrc_count(), 2);
+        assert_eq!(pkt.csrcs().collect::<Vec<_>>(), [11, 12]);
+        assert_eq!(
+            pkt.extension_data(),
+            Some((0xbede, &[0x10, 0xaa, 0, 0][..]))
+        );
+        assert_eq!(pkt.payload(), b"payload");
+        assert_eq!(&pkt.payload_bytes()[..], b"payload");
+        assert_eq!(pkt.data().len(), 38);
+    }
+
+    #[test]
+    fn version() {
+        let err = parse(header(0x40)).unwrap_err();
+        assert_eq!(err, "unsupported version 1");
+    }
+
+    #[test]
+    fn short() {
+        let err = parse(vec![0x80; 11]).unwrap_err();
+        assert!(err.contains("11-byte packet is shorter"), "{}", err);
+    }
+
+    #[test]
+    fn csrc_count_past_end() {
+        let mut data = header(0x8f);
+        data.extend_from_slice(&[0; 56]);
+        let err = parse(data).unwrap_err();
+        assert!(
+            err.contains("68-byte packet too short for 72-byte header"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn truncated_extension() {
+        // No room for the extension header.
+        let mut data = header(0x90);
+        data.extend_from_slice(&[0xbe, 0xde]);
+        let err = parse(data).unwrap_err();
+        assert!(
+            err.contains("too short for extension header at 12"),
+            "{}",
+            err
+        );
+
+        // The extension claims two words but has one.
+        let mut data = header(0x90);
+        data.extend_from_slice(&[0xbe, 0xde, 0, 2, 0, 0, 0, 0]);
+        let err = parse(data).unwrap_err();
+        assert!(
+            err.contains("20-byte packet too short for 24-byte header"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn padding_length_zero() {
+        let mut data = header(0xa0);
+        data.extend_from_slice(&[1, 2, 0]);
+        let err = parse(data).unwrap_err();
+        assert!(err.contains("bad padding length 0"), "{}", err);
+    }
+
+    #[test]
+    fn padding_longer_than_payload() {
+        let mut data = header(0xa0);
+        data.extend_from_slice(&[1, 2, 4]);
+        let err = parse(data).unwrap_err();
+        assert!(err.contains("bad padding length 4 with 3 bytes"), "{}", err);
+
+        // Padding may take up the whole payload.
+        let mut data = header(0xa0);
+        data.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(parse(data).unwrap().payload(), b"");
+    }
+}
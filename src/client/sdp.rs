@@ -0,0 +1,614 @@
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+use rtsp_connection::wrap;
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    codec,
+    error::{Error, ErrorInt},
+};
+
+/// A parsed SDP session description, as returned in a `DESCRIBE` response body.
+#[derive(Clone, Debug, Serialize)]
+pub struct SessionDescription {
+    pub origin: Origin,
+    pub session_name: String,
+    pub connection: Option<ConnectionData>,
+    pub timing: Vec<Timing>,
+    pub attributes: Vec<Attribute>,
+    pub media: Vec<MediaDescription>,
+
+    /// The unparsed body, exactly as received.
+    #[serde(skip)]
+    pub raw: Bytes,
+}
+
+/// The `o=` line.
+#[derive(Clone, Debug, Serialize)]
+pub struct Origin {
+    pub username: Option<String>,
+    pub session_id: String,
+    pub session_version: u64,
+    pub network_type: String,
+    pub address_type: String,
+    pub unicast_address: String,
+}
+
+/// A `c=` line.
+#[derive(Clone, Debug, Serialize)]
+pub struct ConnectionData {
+    pub network_type: String,
+    pub address_type: String,
+    pub address: String,
+}
+
+/// A `t=` line. Both times are NTP seconds; `0` means unbounded.
+#[derive(Clone, Debug, Serialize)]
+pub struct Timing {
+    pub start: u64,
+    pub stop: u64,
+}
+
+/// An `a=` line. `value` is `None` for property attributes such as `a=recvonly`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Attribute {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+/// An `m=` section and everything up to the next one.
+#[derive(Clone, Debug, Serialize)]
+pub struct MediaDescription {
+    pub media: String,
+    pub port: u16,
+    pub num_ports: Option<u16>,
+    pub proto: String,
+    pub formats: Vec<String>,
+    pub title: Option<String>,
+    pub connections: Vec<ConnectionData>,
+    pub attributes: Vec<Attribute>,
+}
+
+/// The kind of media carried by an `m=` section.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    Video,
+    Audio,
+    Application,
+    Other(String),
+}
+
+/// A summary of a single `m=` section's RTP stream.
+#[derive(Clone, Debug, Serialize)]
+pub struct MediaStream {
+    pub kind: MediaKind,
+    pub payload_type: u8,
+
+    /// The encoding name as given in `a=rtpmap`, e.g. `H264` or `mpeg4-generic`.
+    pub encoding_name: String,
+    pub clock_rate: u32,
+    pub channels: Option<u16>,
+
+    /// The `a=fmtp` parameters. Keys are lowercased, as they're case-insensitive.
+    pub format_specific_params: BTreeMap<String, String>,
+
+    /// The `a=control` attribute, unresolved.
+    pub control: Option<String>,
+}
+
+/// Absolute control URLs for a presentation, resolved as described in
+/// [RFC 2326 section C.1.1](https://datatracker.ietf.org/doc/html/rfc2326#appendix-C.1.1).
+#[derive(Clone, Debug, Serialize)]
+pub struct ControlUrls {
+    /// The aggregate control URL, used for session-level requests such as `PLAY`.
+    pub session: Url,
+
+    /// One entry per `m=` section.
+    pub streams: Vec<Url>,
+}
+
+impl ControlUrls {
+    /// Resolves the session's and each media section's `a=control` against the
+    /// base URL of the `DESCRIBE` response.
+    ///
+    /// The base URL is `Content-Base`, then `Content-Location`, then the request URI.
+    pub fn resolve(
+        response: &rtsp_types::Response<Bytes>,
+        request_uri: &Url,
+        sdp: &SessionDescription,
+    ) -> Result<Self, String> {
+        let base = match response
+            .header(&rtsp_types::headers::CONTENT_BASE)
+            .or_else(|| response.header(&rtsp_types::headers::CONTENT_LOCATION))
+        {
+            Some(v) => request_uri
+                .join(v.as_str().trim())
+                .map_err(|e| format!("bad base URL {:?}: {}", v.as_str(), e))?,
+            None => request_uri.clone(),
+        };
+        let session = match sdp.attribute("control") {
+            Some(c) => join_control(&base, c)?,
+            None => base.clone(),
+        };
+        let streams = sdp
+            .media
+            .iter()
+            .map(|m| match m.attribute("control") {
+                None | Some("*") => Ok(session.clone()),
+                Some(c) => join_control(&base, c),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { session, streams })
+    }
+}
+
+/// Joins a control attribute onto the base URL.
+///
+/// RFC 2326 calls for standard relative URL resolution, but cameras commonly send
+/// a base without a trailing slash (`rtsp://host/stream`) or one ending in a query
+/// string (`rtsp://host/cam?channel=1/`) and still expect `trackID=1` to be
+/// appended to it. Like other clients, treat relative controls as a suffix instead.
+fn join_control(base: &Url, control: &str) -> Result<Url, String> {
+    let control = control.trim();
+    if control == "*" {
+        return Ok(base.clone());
+    }
+    if let Ok(absolute) = Url::parse(control) {
+        if matches!(
+            absolute.scheme(),
+            "rtsp" | "rtsps" | "rtspu" | "http" | "https"
+        ) {
+            return Ok(absolute);
+        }
+    }
+    let joined = if control.starts_with('/') {
+        base.join(control)
+    } else if base.as_str().ends_with('/') {
+        Url::parse(&format!("{}{}", base.as_str(), control))
+    } else {
+        Url::parse(&format!("{}/{}", base.as_str(), control))
+    };
+    joined.map_err(|e| format!("can't join control {:?} onto {}: {}", control, base, e))
+}
+
+impl SessionDescription {
+    pub fn parse(raw: Bytes) -> Result<Self, String> {
+        let session = sdp_types::Session::parse(&raw[..]).map_err(|e| e.to_string())?;
+        Ok(Self {
+            origin: Origin {
+                username: session.origin.username,
+                session_id: session.origin.sess_id,
+                session_version: session.origin.sess_version,
+                network_type: session.origin.nettype,
+                address_type: session.origin.addrtype,
+                unicast_address: session.origin.unicast_address,
+            },
+            session_name: session.session_name,
+            connection: session.connection.map(ConnectionData::from),
+            timing: session
+                .times
+                .into_iter()
+                .map(|t| Timing {
+                    start: t.start_time,
+                    stop: t.stop_time,
+                })
+                .collect(),
+            attributes: session
+                .attributes
+                .into_iter()
+                .map(Attribute::from)
+                .collect(),
+            media: session
+                .medias
+                .into_iter()
+                .map(|m| MediaDescription {
+                    formats: m.fmt.split_ascii_whitespace().map(str::to_owned).collect(),
+                    media: m.media,
+                    port: m.port,
+                    num_ports: m.num_ports,
+                    proto: m.proto,
+                    title: m.media_title,
+                    connections: m
+                        .connections
+                        .into_iter()
+                        .map(ConnectionData::from)
+                        .collect(),
+                    attributes: m.attributes.into_iter().map(Attribute::from).collect(),
+                })
+                .collect(),
+            raw,
+        })
+    }
+
+    /// Returns the value of the first session-level attribute called `name`.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        find_attribute(&self.attributes, name)
+    }
+
+    /// Summarizes each `m=` section, in order. A section that can't be
+    /// understood, such as one with a dynamic payload type but no `a=rtpmap`,
+    /// yields an error without affecting the others.
+    pub fn streams(&self) -> Vec<Result<MediaStream, String>> {
+        self.media
+            .iter()
+            .enumerate()
+            .map(|(i, m)| MediaStream::parse(m).map_err(|e| format!("m= section {}: {}", i, e)))
+            .collect()
+    }
+}
+
+impl MediaStream {
+    pub fn parse(media: &MediaDescription) -> Result<Self, String> {
+        let kind = match media.media.as_str() {
+            "video" => MediaKind::Video,
+            "audio" => MediaKind::Audio,
+            "application" => MediaKind::Application,
+            o => MediaKind::Other(o.to_owned()),
+        };
+
+        // RTSP cameras only ever offer a single format per m= section; if there are
+        // several, the first is the preferred one.
+        let format = media
+            .formats
+            .first()
+            .ok_or_else(|| "no formats".to_owned())?;
+        let payload_type = format
+            .parse::<u8>()
+            .ok()
+            .filter(|&pt| pt < 128)
+            .ok_or_else(|| format!("invalid RTP payload type {:?}", format))?;
+
+        let rtpmap = media_format_attribute(media, "rtpmap", format);
+        let (encoding_name, clock_rate, channels) = match rtpmap {
+            Some(rtpmap) => parse_rtpmap(rtpmap)?,
+            None => match static_payload_type(payload_type) {
+                Some((encoding_name, clock_rate, channels)) => {
+                    (encoding_name.to_owned(), clock_rate, channels)
+                }
+                None => {
+                    return Err(format!(
+                        "no rtpmap for dynamic payload type {}",
+                        payload_type
+                    ))
+                }
+            },
+        };
+
+        let format_specific_params = media_format_attribute(media, "fmtp", format)
+            .map(parse_fmtp)
+            .unwrap_or_default();
+
+        Ok(Self {
+            kind,
+            payload_type,
+            encoding_name,
+            clock_rate,
+            channels,
+            format_specific_params,
+            control: media.attribute("control").map(str::to_owned),
+        })
+    }
+
+    /// Returns the `a=fmtp` parameter `name`, which must be given in lowercase.
+    pub fn format_specific_param(&self, name: &str) -> Option<&str> {
+        self.format_specific_params.get(name).map(String::as_str)
+    }
+
+    /// Decodes the codec parameters, if the encoding is one this crate understands.
+    pub fn parameters(&self) -> Result<Option<codec::Parameters>, Error> {
+        codec::Parameters::from_stream(self).map_err(|description| {
+            wrap!(ErrorInt::CodecParametersError {
+                encoding_name: self.encoding_name.clone(),
+                description,
+            })
+        })
+    }
+}
+
+/// Finds an attribute of the form `a=<name>:<format> <rest>`, returning `<rest>`.
+fn media_format_attribute<'a>(
+    media: &'a MediaDescription,
+    name: &str,
+    format: &str,
+) -> Option<&'a str> {
+    media
+        .attributes
+        .iter()
+        .filter(|a| a.name == name)
+        .filter_map(|a| a.value.as_deref())
+        .find_map(|v| {
+            let (f, rest) = v.split_once(' ')?;
+            if f == format {
+                Some(rest.trim())
+            } else {
+                None
+            }
+        })
+}
+
+/// Parses `<encoding name>/<clock rate>[/<channels>]`.
+fn parse_rtpmap(rtpmap: &str) -> Result<(String, u32, Option<u16>), String> {
+    let mut parts = rtpmap.split('/');
+    let encoding_name = parts
+        .next()
+        .filter(|e| !e.is_empty())
+        .ok_or_else(|| format!("rtpmap {:?} has no encoding name", rtpmap))?;
+    let clock_rate = parts
+        .next()
+        .and_then(|c| c.trim().parse::<u32>().ok())
+        .filter(|&c| c > 0)
+        .ok_or_else(|| format!("rtpmap {:?} has no valid clock rate", rtpmap))?;
+    let channels = parts
+        .next()
+        .map(|c| {
+            c.trim()
+                .parse::<u16>()
+                .map_err(|_| format!("rtpmap {:?} has invalid channel count", rtpmap))
+        })
+        .transpose()?;
+    Ok((encoding_name.to_owned(), clock_rate, channels))
+}
+
+/// Parses `key=value; key=value`. Keys without a value map to the empty string.
+fn parse_fmtp(fmtp: &str) -> BTreeMap<String, String> {
+    fmtp.split(';')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| match p.split_once('=') {
+            Some((k, v)) => (k.trim().to_ascii_lowercase(), v.trim().to_owned()),
+            None => (p.to_ascii_lowercase(), String::new()),
+        })
+        .collect()
+}
+
+/// Static payload types from [RFC 3551 section 6](https://datatracker.ietf.org/doc/html/rfc3551#section-6).
+fn static_payload_type(payload_type: u8) -> Option<(&'static str, u32, Option<u16>)> {
+    Some(match payload_type {
+        0 => ("PCMU", 8_000, Some(1)),
+        3 => ("GSM", 8_000, Some(1)),
+        4 => ("G723", 8_000, Some(1)),
+        5 => ("DVI4", 8_000, Some(1)),
+        6 => ("DVI4", 16_000, Some(1)),
+        7 => ("LPC", 8_000, Some(1)),
+        8 => ("PCMA", 8_000, Some(1)),
+        9 => ("G722", 8_000, Some(1)),
+        10 => ("L16", 44_100, Some(2)),
+        11 => ("L16", 44_100, Some(1)),
+        12 => ("QCELP", 8_000, Some(1)),
+        13 => ("CN", 8_000, Some(1)),
+        14 => ("MPA", 90_000, None),
+        15 => ("G728", 8_000, Some(1)),
+        16 => ("DVI4", 11_025, Some(1)),
+        17 => ("DVI4", 22_050, Some(1)),
+        18 => ("G729", 8_000, Some(1)),
+        25 => ("CelB", 90_000, None),
+        26 => ("JPEG", 90_000, None),
+        28 => ("nv", 90_000, None),
+        31 => ("H261", 90_000, None),
+        32 => ("MPV", 90_000, None),
+        33 => ("MP2T", 90_000, None),
+        34 => ("H263", 90_000, None),
+        _ => return None,
+    })
+}
+
+impl MediaDescription {
+    /// Returns the value of the first media-level attribute called `name`.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        find_attribute(&self.attributes, name)
+    }
+}
+
+fn find_attribute<'a>(attributes: &'a [Attribute], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|a| a.name == name)
+        .and_then(|a| a.value.as_deref())
+}
+
+impl From<sdp_types::Connection> for ConnectionData {
+    fn from(c: sdp_types::Connection) -> Self {
+        Self {
+            network_type: c.nettype,
+            address_type: c.addrtype,
+            address: c.connection_address,
+        }
+    }
+}
+
+impl From<sdp_types::Attribute> for Attribute {
+    fn from(a: sdp_types::Attribute) -> Self {
+        Self {
+            name: a.attribute,
+            value: a.value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(sdp: &str) -> SessionDescription {
+        SessionDescription::parse(Bytes::from(sdp.replace('\n', "\r\n"))).unwrap()
+    }
+
+    #[test]
+    fn bad_media_section_keeps_others() {
+        let sdp = parse(
+            "v=0
+o=- 1 1 IN IP4 192.0.2.1
+s=Session
+t=0 0
+m=video 0 RTP/AVP 96
+a=rtpmap:96 H264/90000
+a=control:trackID=1
+m=application 0 RTP/AVP 107
+a=control:trackID=2
+",
+        );
+        let streams = sdp.streams();
+        assert_eq!(streams.len(), 2);
+        let video = streams[0].as_ref().unwrap();
+        assert_eq!(video.encoding_name, "H264");
+        assert_eq!(video.clock_rate, 90_000);
+        assert_eq!(
+            streams[1].as_ref().unwrap_err(),
+            "m= section 1: no rtpmap for dynamic payload type 107"
+        );
+    }
+
+    /// A session with one stream per control attribute, and a session-level
+    /// control if `session_control` is given.
+    fn with_controls(session_control: Option<&str>, controls: &[&str]) -> SessionDescription {
+        let mut sdp = "v=0\no=- 1 1 IN IP4 192.0.2.1\ns=Session\nt=0 0\n".to_owned();
+        if let Some(c) = session_control {
+            sdp.push_str(&format!("a=control:{}\n", c));
+        }
+        for c in controls {
+            sdp.push_str(&format!(
+                "m=video 0 RTP/AVP 96\na=rtpmap:96 H264/90000\na=control:{}\n",
+                c
+            ));
+        }
+        parse(&sdp)
+    }
+
+    fn response(headers: &[(rtsp_types::HeaderName, &str)]) -> rtsp_types::Response<Bytes> {
+        let mut builder =
+            rtsp_types::Response::builder(rtsp_types::Version::V1_0, rtsp_types::StatusCode::Ok);
+        for (name, value) in headers {
+            builder = builder.header(name.clone(), *value);
+        }
+        builder.build(Bytes::new())
+    }
+
+    fn resolve(
+        headers: &[(rtsp_types::HeaderName, &str)],
+        sdp: &SessionDescription,
+    ) -> ControlUrls {
+        let request_uri = Url::parse("rtsp://192.0.2.1/request").unwrap();
+        ControlUrls::resolve(&response(headers), &request_uri, sdp).unwrap()
+    }
+
+    fn strs(urls: &[Url]) -> Vec<&str> {
+        urls.iter().map(Url::as_str).collect()
+    }
+
+    #[test]
+    fn base_without_trailing_slash() {
+        let sdp = with_controls(None, &["trackID=1", "trackID=2"]);
+        let urls = resolve(
+            &[(rtsp_types::headers::CONTENT_BASE, "rtsp://192.0.2.1/stream")],
+            &sdp,
+        );
+        assert_eq!(urls.session.as_str(), "rtsp://192.0.2.1/stream");
+        assert_eq!(
+            strs(&urls.streams),
+            [
+                "rtsp://192.0.2.1/stream/trackID=1",
+                "rtsp://192.0.2.1/stream/trackID=2"
+            ]
+        );
+    }
+
+    #[test]
+    fn base_with_trailing_slash() {
+        let sdp = with_controls(None, &["trackID=1"]);
+        let urls = resolve(
+            &[(
+                rtsp_types::headers::CONTENT_BASE,
+                "rtsp://192.0.2.1/stream/",
+            )],
+            &sdp,
+        );
+        assert_eq!(strs(&urls.streams), ["rtsp://192.0.2.1/stream/trackID=1"]);
+    }
+
+    #[test]
+    fn base_with_query() {
+        let sdp = with_controls(None, &["trackID=1"]);
+        let urls = resolve(
+            &[(
+                rtsp_types::headers::CONTENT_BASE,
+                "rtsp://192.0.2.1/cam?channel=1/",
+            )],
+            &sdp,
+        );
+        assert_eq!(
+            strs(&urls.streams),
+            ["rtsp://192.0.2.1/cam?channel=1/trackID=1"]
+        );
+    }
+
+    #[test]
+    fn absolute_control() {
+        let sdp = with_controls(
+            Some("rtsp://192.0.2.2/session"),
+            &["rtsp://192.0.2.2/session/video"],
+        );
+        let urls = resolve(
+            &[(
+                rtsp_types::headers::CONTENT_BASE,
+                "rtsp://192.0.2.1/stream/",
+            )],
+            &sdp,
+        );
+        assert_eq!(urls.session.as_str(), "rtsp://192.0.2.2/session");
+        assert_eq!(strs(&urls.streams), ["rtsp://192.0.2.2/session/video"]);
+    }
+
+    #[test]
+    fn star_control() {
+        let sdp = with_controls(Some("*"), &["*"]);
+        let urls = resolve(
+            &[(
+                rtsp_types::headers::CONTENT_BASE,
+                "rtsp://192.0.2.1/stream/",
+            )],
+            &sdp,
+        );
+        assert_eq!(urls.session.as_str(), "rtsp://192.0.2.1/stream/");
+        assert_eq!(strs(&urls.streams), ["rtsp://192.0.2.1/stream/"]);
+    }
+
+    #[test]
+    fn content_base_preferred_over_content_location() {
+        let sdp = with_controls(None, &["trackID=1"]);
+        let urls = resolve(
+            &[
+                (
+                    rtsp_types::headers::CONTENT_LOCATION,
+                    "rtsp://192.0.2.1/location/",
+                ),
+                (rtsp_types::headers::CONTENT_BASE, "rtsp://192.0.2.1/base/"),
+            ],
+            &sdp,
+        );
+        assert_eq!(strs(&urls.streams), ["rtsp://192.0.2.1/base/trackID=1"]);
+    }
+
+    #[test]
+    fn content_location_fallback() {
+        let sdp = with_controls(None, &["trackID=1"]);
+        let urls = resolve(
+            &[(
+                rtsp_types::headers::CONTENT_LOCATION,
+                "rtsp://192.0.2.1/location/",
+            )],
+            &sdp,
+        );
+        assert_eq!(strs(&urls.streams), ["rtsp://192.0.2.1/location/trackID=1"]);
+    }
+
+    #[test]
+    fn request_uri_fallback() {
+        let sdp = with_controls(None, &["trackID=1"]);
+        let urls = resolve(&[], &sdp);
+        assert_eq!(urls.session.as_str(), "rtsp://192.0.2.1/request");
+        assert_eq!(strs(&urls.streams), ["rtsp://192.0.2.1/request/trackID=1"]);
+    }
+}
@@ -0,0 +1,274 @@
+//! Per-SSRC reception statistics for RTP streams.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use super::rtp::RtpPacket;
+
+/// How many recent sequence numbers to remember for detecting duplicates.
+const DUPLICATE_WINDOW: usize = 128;
+
+/// Reception statistics for every SSRC seen, keyed by SSRC.
+#[derive(Debug)]
+pub struct Statistics {
+    /// The span over which bitrate is computed.
+    bitrate_window: Duration,
+    sources: BTreeMap<u32, Source>,
+}
+
+#[derive(Debug)]
+struct Source {
+    track: usize,
+    clock_rate: Option<u32>,
+    first_received: Instant,
+    packets: u64,
+    bytes: u64,
+    max_seq: u16,
+    gaps: u64,
+    lost: u64,
+    reorders: u64,
+    duplicates: u64,
+    recent_seqs: VecDeque<u16>,
+
+    /// The arrival time in timestamp units and RTP timestamp of the last
+    /// packet, for jitter.
+    last_transit: Option<(f64, u32)>,
+
+    /// Interarrival jitter in timestamp units, as in RFC 3550 section 6.4.1.
+    jitter: f64,
+
+    /// Arrival times and sizes of packets within the bitrate window.
+    window: VecDeque<(Instant, usize)>,
+}
+
+/// Statistics for one SSRC at a point in time.
+#[derive(Clone, Debug, Serialize)]
+pub struct SourceSnapshot {
+    pub ssrc: u32,
+
+    /// The index of the stream within the session description.
+    pub track: usize,
+    pub packets: u64,
+
+    /// Payload and header bytes received.
+    pub bytes: u64,
+
+    /// Jumps forward in sequence number skipping at least one packet.
+    pub gaps: u64,
+
+    /// Packets skipped by gaps and not later received out of order.
+    pub lost: u64,
+
+    /// Packets received after a later sequence number.
+    pub reorders: u64,
+    pub duplicates: u64,
+
+    /// Interarrival jitter in milliseconds, if the clock rate is known.
+    pub jitter_ms: Option<f64>,
+
+    /// Bits per second over the bitrate window.
+    pub bitrate_bps: f64,
+}
+
+impl Statistics {
+    pub fn new(bitrate_window: Duration) -> Self {
+        Statistics {
+            bitrate_window,
+            sources: BTreeMap::new(),
+        }
+    }
+
+    /// Records a packet of the given track, received at `received`.
+    pub fn record(
+        &mut self,
+        track: usize,
+        clock_rate: Option<u32>,
+        pkt: &RtpPacket,
+        received: Instant,
+    ) {
+        let seq = pkt.sequence_number();
+        let source = self.sources.entry(pkt.ssrc()).or_insert_with(|| Source {
+            track,
+            clock_rate,
+            first_received: received,
+            packets: 0,
+            bytes: 0,
+            max_seq: seq.wrapping_sub(1),
+            gaps: 0,
+            lost: 0,
+            reorders: 0,
+            duplicates: 0,
+            recent_seqs: VecDeque::with_capacity(DUPLICATE_WINDOW),
+            last_transit: None,
+            jitter: 0.,
+            window: VecDeque::new(),
+        });
+        source.packets += 1;
+        source.bytes += pkt.data().len() as u64;
+        source.window.push_back((received, pkt.data().len()));
+        while let Some(&(t, _)) = source.window.front() {
+            if received.duration_since(t) <= self.bitrate_window {
+                break;
+            }
+            source.window.pop_front();
+        }
+
+        // As in RFC 3550 appendix A.8, every packet counts towards jitter,
+        // whatever its sequence number.
+        if let Some(clock_rate) = source.clock_rate {
+            let arrival = received.duration_since(source.first_received).as_secs_f64()
+                * f64::from(clock_rate);
+            let timestamp = pkt.timestamp();
+            if let Some((last_arrival, last_timestamp)) = source.last_transit {
+                let d = (arrival - last_arrival)
+                    - f64::from(timestamp.wrapping_sub(last_timestamp) as i32);
+                source.jitter += (d.abs() - source.jitter) / 16.;
+            }
+            source.last_transit = Some((arrival, timestamp));
+        }
+
+        if source.recent_seqs.contains(&seq) {
+            source.duplicates += 1;
+            return;
+        }
+        if source.recent_seqs.len() == DUPLICATE_WINDOW {
+            source.recent_seqs.pop_front();
+        }
+        source.recent_seqs.push_back(seq);
+
+        let delta = seq.wrapping_sub(source.max_seq) as i16;
+        if delta <= 0 {
+            source.reorders += 1;
+            source.lost = source.lost.saturating_sub(1);
+            return;
+        }
+        if delta > 1 {
+            source.gaps += 1;
+            source.lost += (delta - 1) as u64;
+        }
+        source.max_seq = seq;
+    }
+
+    /// Returns statistics for each SSRC as of `now`, ordered by SSRC.
+    pub fn snapshot(&self, now: Instant) -> Vec<SourceSnapshot> {
+        self.sources
+            .iter()
+            .map(|(&ssrc, s)| {
+                let window_bytes: usize = s
+                    .window
+                    .iter()
+                    .filter(|(t, _)| now.saturating_duration_since(*t) <= self.bitrate_window)
+                    .map(|(_, len)| len)
+                    .sum();
+                // Until a full window has passed, divide by the time so far.
+                let span = now
+                    .saturating_duration_since(s.first_received)
+                    .min(self.bitrate_window)
+                    .as_secs_f64();
+                SourceSnapshot {
+                    ssrc,
+                    track: s.track,
+                    packets: s.packets,
+                    bytes: s.bytes,
+                    gaps: s.gaps,
+                    lost: s.lost,
+                    reorders: s.reorders,
+                    duplicates: s.duplicates,
+                    jitter_ms: s.clock_rate.map(|r| s.jitter * 1000. / f64::from(r)),
+                    bitrate_bps: if span > 0. {
+                        8. * window_bytes as f64 / span
+                    } else {
+                        0.
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::depacketize::testutil::rtp;
+
+    /// Records packets of `(sequence number, timestamp, milliseconds after the
+    /// first)` with a 1 kHz clock, returning the only source's statistics.
+    fn record(packets: &[(u16, u32, u64)]) -> SourceSnapshot {
+        let mut stats = Statistics::new(Duration::from_secs(1));
+        let start = Instant::now();
+        for &(seq, ts, ms) in packets {
+            let pkt = rtp(seq, ts, false, &[0; 100]);
+            stats.record(0, Some(1000), &pkt, start + Duration::from_millis(ms));
+        }
+        let mut snapshot = stats.snapshot(start);
+        assert_eq!(snapshot.len(), 1);
+        snapshot.pop().unwrap()
+    }
+
+    fn assert_jitter(s: &SourceSnapshot, expected_ms: f64) {
+        let jitter = s.jitter_ms.unwrap();
+        assert!((jitter - expected_ms).abs() < 1e-6, "{}", jitter);
+    }
+
+    #[test]
+    fn gap() {
+        let s = record(&[(1, 0, 0), (2, 0, 0), (5, 0, 0)]);
+        assert_eq!((s.packets, s.gaps, s.lost, s.reorders), (3, 1, 2, 0));
+    }
+
+    #[test]
+    fn reorder() {
+        // 2 is first counted lost, then arrives late.
+        let s = record(&[(1, 0, 0), (3, 0, 0), (2, 0, 0)]);
+        assert_eq!((s.packets, s.gaps, s.lost, s.reorders), (3, 1, 0, 1));
+    }
+
+    #[test]
+    fn duplicate() {
+        let s = record(&[(1, 0, 0), (2, 0, 0), (2, 0, 0), (1, 0, 0)]);
+        assert_eq!((s.packets, s.duplicates, s.reorders), (4, 2, 0));
+        assert_eq!(s.bytes, 4 * 112);
+    }
+
+    #[test]
+    fn sequence_wraparound() {
+        let s = record(&[(65534, 0, 0), (65535, 0, 0), (0, 0, 0), (2, 0, 0)]);
+        assert_eq!((s.gaps, s.lost, s.reorders), (1, 1, 0));
+    }
+
+    #[test]
+    fn jitter() {
+        // Transit times of 0, 0 and 5 ms: D is 0, then 5, so J is 5/16.
+        let s = record(&[(1, 0, 0), (2, 10, 10), (3, 20, 25)]);
+        assert_jitter(&s, 5. / 16.);
+
+        // A late packet counts too: from 3 to 2, D is (30 - 20) - (10 - 20).
+        let s = record(&[(1, 0, 0), (3, 20, 20), (2, 10, 30)]);
+        assert_jitter(&s, 20. / 16.);
+
+        // Without a clock rate there's no jitter.
+        let mut stats = Statistics::new(Duration::from_secs(1));
+        stats.record(0, None, &rtp(1, 0, false, &[]), Instant::now());
+        assert_eq!(stats.snapshot(Instant::now())[0].jitter_ms, None);
+    }
+
+    #[test]
+    fn bitrate() {
+        let mut stats = Statistics::new(Duration::from_secs(1));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        stats.record(0, None, &rtp(1, 0, false, &[0; 100]), at(0));
+        stats.record(0, None, &rtp(2, 0, false, &[0; 100]), at(500));
+
+        // Two 112-byte packets over the half second so far.
+        assert_eq!(stats.snapshot(at(500))[0].bitrate_bps, 8. * 224. / 0.5);
+
+        // Only the second is within the last second.
+        assert_eq!(stats.snapshot(at(1400))[0].bitrate_bps, 8. * 112.);
+        assert_eq!(stats.snapshot(at(0))[0].bitrate_bps, 0.);
+    }
+}
@@ -0,0 +1,226 @@
+//! `Transport` and `Session` headers, as in
+//! [RFC 2326 section 12](https://datatracker.ietf.org/doc/html/rfc2326#section-12).
+
+use std::{str::FromStr, time::Duration};
+
+use serde::Serialize;
+
+/// The session timeout to assume when the `Session` header doesn't give one.
+const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A transport to offer in `SETUP`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TransportCandidate {
+    /// RTP and RTCP interleaved on the RTSP connection.
+    TcpInterleaved,
+
+    /// RTP and RTCP over UDP to ports chosen by the client.
+    UdpUnicast,
+
+    /// RTP and RTCP over UDP to a multicast group chosen by the server.
+    Multicast,
+}
+
+impl TransportCandidate {
+    /// Returns the `Transport` header value offering this for the track with
+    /// index `track`, which determines the channels or ports requested.
+    ///
+    /// The UDP ports are nominal: nothing listens on them, so this is suitable
+    /// only for discovering what the server accepts.
+    pub fn header(&self, track: usize) -> String {
+        match self {
+            TransportCandidate::TcpInterleaved => format!(
+                "RTP/AVP/TCP;unicast;interleaved={}-{}",
+                2 * track,
+                2 * track + 1
+            ),
+            TransportCandidate::UdpUnicast => format!(
+                "RTP/AVP;unicast;client_port={}-{}",
+                5000 + 2 * track,
+                5000 + 2 * track + 1
+            ),
+            TransportCandidate::Multicast => "RTP/AVP;multicast".to_owned(),
+        }
+    }
+}
+
+impl FromStr for TransportCandidate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tcp" | "tcp-interleaved" => Ok(TransportCandidate::TcpInterleaved),
+            "udp" | "udp-unicast" => Ok(TransportCandidate::UdpUnicast),
+            "multicast" => Ok(TransportCandidate::Multicast),
+            _ => Err(format!(
+                "unknown transport {:?}; expected tcp, udp or multicast",
+                s
+            )),
+        }
+    }
+}
+
+/// The transport a server accepted, from a `SETUP` response's `Transport` header.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct NegotiatedTransport {
+    /// The header as sent.
+    pub raw: String,
+
+    /// The transport protocol, such as `RTP/AVP/TCP`.
+    pub protocol: String,
+    pub multicast: bool,
+    pub interleaved: Option<(u8, u8)>,
+    pub client_port: Option<(u16, u16)>,
+    pub server_port: Option<(u16, u16)>,
+    pub ssrc: Option<u32>,
+    pub source: Option<String>,
+    pub destination: Option<String>,
+
+    /// For multicast, the group's ports.
+    pub port: Option<(u16, u16)>,
+    pub ttl: Option<u8>,
+}
+
+impl NegotiatedTransport {
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        // A server may echo back several comma-separated transports; the first
+        // is the one it chose.
+        let chosen = raw.split(',').next().unwrap_or_default().trim();
+        let mut parts = chosen.split(';').map(str::trim);
+        let mut t = NegotiatedTransport {
+            raw: raw.to_owned(),
+            protocol: parts.next().unwrap_or_default().to_owned(),
+            ..Default::default()
+        };
+        if !t.protocol.starts_with("RTP/AVP") {
+            return Err(format!("unsupported transport protocol {:?}", t.protocol));
+        }
+        for part in parts {
+            let (key, value) = match part.split_once('=') {
+                Some((k, v)) => (k, Some(v.trim_matches('"'))),
+                None => (part, None),
+            };
+            let value = || value.ok_or_else(|| format!("{} without value", key));
+            match key.to_ascii_lowercase().as_str() {
+                "multicast" => t.multicast = true,
+                "interleaved" => t.interleaved = Some(parse_range(value()?)?),
+                "client_port" => t.client_port = Some(parse_range(value()?)?),
+                "server_port" => t.server_port = Some(parse_range(value()?)?),
+                "port" => t.port = Some(parse_range(value()?)?),
+                "ssrc" => {
+                    let v = value()?;
+                    t.ssrc = Some(
+                        u32::from_str_radix(v, 16).map_err(|_| format!("bad ssrc {:?}", v))?,
+                    );
+                }
+                "source" => t.source = Some(value()?.to_owned()),
+                "destination" => t.destination = Some(value()?.to_owned()),
+                "ttl" => {
+                    let v = value()?;
+                    t.ttl = Some(v.parse().map_err(|_| format!("bad ttl {:?}", v))?);
+                }
+                _ => {}
+            }
+        }
+        Ok(t)
+    }
+}
+
+/// Parses `a-b`, or `a` meaning `a-(a+1)`.
+fn parse_range<T: FromStr + Copy + Into<u32> + TryFrom<u32>>(v: &str) -> Result<(T, T), String> {
+    let bad = || format!("bad range {:?}", v);
+    match v.split_once('-') {
+        Some((a, b)) => Ok((a.parse().map_err(|_| bad())?, b.parse().map_err(|_| bad())?)),
+        None => {
+            let a: T = v.parse().map_err(|_| bad())?;
+            let b = T::try_from(a.into() + 1).map_err(|_| bad())?;
+            Ok((a, b))
+        }
+    }
+}
+
+/// An RTSP session established by `SETUP`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Session {
+    pub id: String,
+
+    /// How long the server keeps the session without activity.
+    #[serde(rename = "timeout_secs", serialize_with = "serialize_secs")]
+    pub timeout: Duration,
+}
+
+fn serialize_secs<S: serde::Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u64(d.as_secs())
+}
+
+impl Session {
+    /// Parses a `Session` header value such as `12345678;timeout=60`.
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let mut parts = raw.split(';').map(str::trim);
+        let id = parts.next().unwrap_or_default();
+        if id.is_empty() {
+            return Err("empty session id".to_owned());
+        }
+        let mut timeout = DEFAULT_SESSION_TIMEOUT;
+        for part in parts {
+            if let Some(v) = part.strip_prefix("timeout=") {
+                let secs: u64 = v.parse().map_err(|_| format!("bad timeout {:?}", v))?;
+                if secs == 0 {
+                    return Err("zero timeout".to_owned());
+                }
+                timeout = Duration::from_secs(secs);
+            }
+        }
+        Ok(Session {
+            id: id.to_owned(),
+            timeout,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interleaved() {
+        let t = NegotiatedTransport::parse(
+            "RTP/AVP/TCP;unicast;interleaved=0-1;ssrc=1A2B3C4D;mode=\"PLAY\"",
+        )
+        .unwrap();
+        assert_eq!(t.protocol, "RTP/AVP/TCP");
+        assert_eq!(t.interleaved, Some((0, 1)));
+        assert_eq!(t.ssrc, Some(0x1a2b_3c4d));
+    }
+
+    #[test]
+    fn single_value_ranges() {
+        let t = NegotiatedTransport::parse(
+            "RTP/AVP;unicast;client_port=5000;server_port=65534, RTP/AVP/TCP",
+        )
+        .unwrap();
+        assert_eq!(t.client_port, Some((5000, 5001)));
+        assert_eq!(t.server_port, Some((65534, 65535)));
+    }
+
+    #[test]
+    fn range_overflow() {
+        NegotiatedTransport::parse("RTP/AVP/TCP;interleaved=255").unwrap_err();
+        NegotiatedTransport::parse("RTP/AVP;server_port=65535").unwrap_err();
+        let t = NegotiatedTransport::parse("RTP/AVP/TCP;interleaved=254").unwrap();
+        assert_eq!(t.interleaved, Some((254, 255)));
+    }
+
+    #[test]
+    fn session() {
+        let s = Session::parse("12345678;timeout=30").unwrap();
+        assert_eq!(s.id, "12345678");
+        assert_eq!(s.timeout, Duration::from_secs(30));
+        assert_eq!(
+            Session::parse("abc").unwrap().timeout,
+            DEFAULT_SESSION_TIMEOUT
+        );
+        Session::parse("abc;timeout=0").unwrap_err();
+    }
+}
@@ -0,0 +1,325 @@
+//! MPEG-4 AAC parameters, for both `mpeg4-generic`
+//! ([RFC 3640](https://datatracker.ietf.org/doc/html/rfc3640)) and `MP4A-LATM`
+//! ([RFC 6416](https://datatracker.ietf.org/doc/html/rfc6416)) payloads.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use super::bits::BitReader;
+
+/// Parameters from an AAC media section's `a=fmtp` line.
+#[derive(Clone, Debug, Serialize)]
+pub struct Parameters {
+    pub config: AudioSpecificConfig,
+
+    /// How access units are framed within RTP packets.
+    pub framing: Framing,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "framing", rename_all = "lowercase")]
+pub enum Framing {
+    /// `mpeg4-generic`, with AU headers described by these fmtp parameters.
+    Generic {
+        mode: String,
+        size_length: Option<u8>,
+        index_length: Option<u8>,
+        index_delta_length: Option<u8>,
+    },
+
+    /// `MP4A-LATM`. If `cpresent` is true, the `StreamMuxConfig` is repeated in-band.
+    Latm { cpresent: bool, num_sub_frames: u8 },
+}
+
+/// The leading fields of an `AudioSpecificConfig`, as in ISO/IEC 14496-3 section 1.6.2.1.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct AudioSpecificConfig {
+    pub audio_object_type: u8,
+    pub sampling_frequency: u32,
+    pub channel_configuration: u8,
+
+    /// For SBR/PS streams signalled explicitly, the output sampling frequency.
+    pub extension_sampling_frequency: Option<u32>,
+}
+
+impl Parameters {
+    /// Parses `mpeg4-generic` fmtp parameters.
+    pub fn parse_generic(
+        format_specific_params: &BTreeMap<String, String>,
+    ) -> Result<Self, String> {
+        let mode = format_specific_params
+            .get("mode")
+            .ok_or_else(|| "missing mode".to_owned())?;
+        let config = format_specific_params
+            .get("config")
+            .ok_or_else(|| "missing config".to_owned())?;
+        let config = decode_hex(config).map_err(|e| format!("bad config {:?}: {}", config, e))?;
+        let config = AudioSpecificConfig::parse(&config)
+            .map_err(|e| format!("bad AudioSpecificConfig: {}", e))?;
+        let length = |name: &str| {
+            format_specific_params
+                .get(name)
+                .map(|v| {
+                    v.parse::<u8>()
+                        .ok()
+                        .filter(|&l| l <= 32)
+                        .ok_or_else(|| format!("bad {} {:?}", name, v))
+                })
+                .transpose()
+        };
+        let size_length = length("sizelength")?;
+        let index_length = length("indexlength")?;
+        let index_delta_length = length("indexdeltalength")?;
+        if (mode.eq_ignore_ascii_case("AAC-hbr") || mode.eq_ignore_ascii_case("AAC-lbr"))
+            && (size_length.is_none() || index_length.is_none() || index_delta_length.is_none())
+        {
+            return Err(format!(
+                "mode {} requires sizeLength, indexLength and indexDeltaLength",
+                mode
+            ));
+        }
+        Ok(Self {
+            config,
+            framing: Framing::Generic {
+                mode: mode.clone(),
+                size_length,
+                index_length,
+                index_delta_length,
+            },
+        })
+    }
+
+    /// Parses `MP4A-LATM` fmtp parameters.
+    pub fn parse_latm(format_specific_params: &BTreeMap<String, String>) -> Result<Self, String> {
+        let cpresent = match format_specific_params.get("cpresent").map(String::as_str) {
+            None | Some("1") => true,
+            Some("0") => false,
+            Some(o) => return Err(format!("bad cpresent {:?}", o)),
+        };
+        let config = format_specific_params
+            .get("config")
+            .ok_or_else(|| "missing config; in-band StreamMuxConfig isn't supported".to_owned())?;
+        let config = decode_hex(config).map_err(|e| format!("bad config {:?}: {}", config, e))?;
+        let (num_sub_frames, config) =
+            parse_stream_mux_config(&config).map_err(|e| format!("bad StreamMuxConfig: {}", e))?;
+        Ok(Self {
+            config,
+            framing: Framing::Latm {
+                cpresent,
+                num_sub_frames,
+            },
+        })
+    }
+}
+
+impl AudioSpecificConfig {
+    pub fn parse(config: &[u8]) -> Result<Self, String> {
+        Self::read(&mut BitReader::new(config))
+    }
+
+    fn read(r: &mut BitReader) -> Result<Self, String> {
+        let mut audio_object_type = read_audio_object_type(r)?;
+        let sampling_frequency = read_sampling_frequency(r)?;
+        let channel_configuration = r.read_u8(4)?;
+        if channel_configuration > 7 {
+            return Err(format!(
+                "reserved channel configuration {}",
+                channel_configuration
+            ));
+        }
+        let mut extension_sampling_frequency = None;
+        if audio_object_type == 5 || audio_object_type == 29 {
+            // Explicit SBR (5) or PS (29) signalling: the core object type follows.
+            extension_sampling_frequency = Some(read_sampling_frequency(r)?);
+            audio_object_type = read_audio_object_type(r)?;
+        }
+        if audio_object_type == 0 {
+            return Err("null audio object type".to_owned());
+        }
+        Ok(Self {
+            audio_object_type,
+            sampling_frequency,
+            channel_configuration,
+            extension_sampling_frequency,
+        })
+    }
+
+    pub fn object_type_name(&self) -> &'static str {
+        match self.audio_object_type {
+            1 => "AAC Main",
+            2 => "AAC LC",
+            3 => "AAC SSR",
+            4 => "AAC LTP",
+            6 => "AAC Scalable",
+            17 => "ER AAC LC",
+            23 => "ER AAC LD",
+            39 => "ER AAC ELD",
+            _ => "Unknown",
+        }
+    }
+
+    /// Returns the channel count, or `None` if it's given in a program config element.
+    pub fn channels(&self) -> Option<u16> {
+        match self.channel_configuration {
+            0 => None,
+            7 => Some(8),
+            c => Some(u16::from(c)),
+        }
+    }
+}
+
+fn read_audio_object_type(r: &mut BitReader) -> Result<u8, String> {
+    match r.read_u8(5)? {
+        31 => Ok(32 + r.read_u8(6)?),
+        t => Ok(t),
+    }
+}
+
+fn read_sampling_frequency(r: &mut BitReader) -> Result<u32, String> {
+    const FREQUENCIES: [u32; 13] = [
+        96_000, 88_200, 64_000, 48_000, 44_100, 32_000, 24_000, 22_050, 16_000, 12_000, 11_025,
+        8_000, 7_350,
+    ];
+    match r.read_u8(4)? {
+        0xf => r.read_bits(24),
+        i => FREQUENCIES
+            .get(usize::from(i))
+            .copied()
+            .ok_or_else(|| format!("reserved sampling frequency index {}", i)),
+    }
+}
+
+/// Parses a `StreamMuxConfig` as in ISO/IEC 14496-3 section 1.7.3.1, returning
+/// `numSubFrames + 1` and the first layer's `AudioSpecificConfig`.
+fn parse_stream_mux_config(config: &[u8]) -> Result<(u8, AudioSpecificConfig), String> {
+    let mut r = BitReader::new(config);
+    let audio_mux_version = r.read_bit()?;
+    if audio_mux_version && r.read_bit()? {
+        return Err("audioMuxVersionA 1 is reserved".to_owned());
+    }
+    if audio_mux_version {
+        latm_get_value(&mut r)?; // taraBufferFullness
+    }
+    r.read_bit()?; // allStreamsSameTimeFraming
+    let num_sub_frames = r.read_u8(6)? + 1;
+    let num_program = r.read_u8(4)?;
+    let num_layer = r.read_u8(3)?;
+    if num_program != 0 || num_layer != 0 {
+        return Err(format!(
+            "{} programs with {} layers aren't supported",
+            u16::from(num_program) + 1,
+            u16::from(num_layer) + 1
+        ));
+    }
+    let config = if audio_mux_version {
+        let asc_len = latm_get_value(&mut r)?;
+        let config = AudioSpecificConfig::read(&mut r)?;
+        if asc_len == 0 {
+            return Err("empty AudioSpecificConfig".to_owned());
+        }
+        config
+    } else {
+        AudioSpecificConfig::read(&mut r)?
+    };
+    Ok((num_sub_frames, config))
+}
+
+fn latm_get_value(r: &mut BitReader) -> Result<u32, String> {
+    let bytes_for_value = r.read_bits(2)?;
+    let mut value = 0;
+    for _ in 0..=bytes_for_value {
+        value = (value << 8) | r.read_bits(8)?;
+    }
+    Ok(value)
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err("not an even number of hex digits".to_owned());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("bad hex at {}", i)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmtp(params: &[(&str, &str)]) -> BTreeMap<String, String> {
+        params
+            .iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn generic_lc_48k_stereo() {
+        let p = Parameters::parse_generic(&fmtp(&[
+            ("mode", "AAC-hbr"),
+            ("config", "1190"),
+            ("sizelength", "13"),
+            ("indexlength", "3"),
+            ("indexdeltalength", "3"),
+        ]))
+        .unwrap();
+        assert_eq!(p.config.audio_object_type, 2);
+        assert_eq!(p.config.object_type_name(), "AAC LC");
+        assert_eq!(p.config.sampling_frequency, 48_000);
+        assert_eq!(p.config.channels(), Some(2));
+        assert_eq!(p.config.extension_sampling_frequency, None);
+        match p.framing {
+            Framing::Generic {
+                size_length,
+                index_length,
+                index_delta_length,
+                ..
+            } => assert_eq!(
+                (size_length, index_length, index_delta_length),
+                (Some(13), Some(3), Some(3))
+            ),
+            f => panic!("unexpected framing {:?}", f),
+        }
+    }
+
+    #[test]
+    fn generic_hbr_requires_lengths() {
+        Parameters::parse_generic(&fmtp(&[("mode", "AAC-hbr"), ("config", "1190")])).unwrap_err();
+    }
+
+    #[test]
+    fn explicit_sbr() {
+        // HE-AAC: SBR at 24 kHz mono with a 48 kHz output, then AAC LC.
+        let config = AudioSpecificConfig::parse(&[0x2b, 0x09, 0x88]).unwrap();
+        assert_eq!(config.audio_object_type, 2);
+        assert_eq!(config.sampling_frequency, 24_000);
+        assert_eq!(config.extension_sampling_frequency, Some(48_000));
+        assert_eq!(config.channels(), Some(1));
+    }
+
+    #[test]
+    fn latm() {
+        let p =
+            Parameters::parse_latm(&fmtp(&[("cpresent", "0"), ("config", "40002420")])).unwrap();
+        assert_eq!(p.config.audio_object_type, 2);
+        assert_eq!(p.config.sampling_frequency, 44_100);
+        assert_eq!(p.config.channels(), Some(2));
+        match p.framing {
+            Framing::Latm {
+                cpresent,
+                num_sub_frames,
+            } => assert_eq!((cpresent, num_sub_frames), (false, 1)),
+            f => panic!("unexpected framing {:?}", f),
+        }
+    }
+
+    #[test]
+    fn bad_hex() {
+        decode_hex("119").unwrap_err();
+        decode_hex("11g0").unwrap_err();
+        assert_eq!(decode_hex(" 1190 ").unwrap(), [0x11, 0x90]);
+    }
+}
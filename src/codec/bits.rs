@@ -0,0 +1,115 @@
+/// Strips emulation prevention bytes (the `03` in `00 00 03`) from a NAL unit.
+pub(crate) fn decode_rbsp(nal: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &b in nal {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        rbsp.push(b);
+    }
+    rbsp
+}
+
+/// A most-significant-bit-first reader over an RBSP, with Exp-Golomb support.
+pub(crate) struct BitReader<'a> {
+    data: &'a [u8],
+
+    /// The position of the next bit to read.
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub(crate) fn read_bit(&mut self) -> Result<bool, String> {
+        let byte = self
+            .data
+            .get(self.pos / 8)
+            .ok_or_else(|| format!("unexpected end of data at bit {}", self.pos))?;
+        let bit = (byte >> (7 - (self.pos % 8))) & 1;
+        self.pos += 1;
+        Ok(bit == 1)
+    }
+
+    /// Reads `n <= 32` bits as an unsigned integer.
+    pub(crate) fn read_bits(&mut self, n: u32) -> Result<u32, String> {
+        debug_assert!(n <= 32);
+        let mut v = 0u64;
+        for _ in 0..n {
+            v = (v << 1) | u64::from(self.read_bit()?);
+        }
+        Ok(v as u32)
+    }
+
+    pub(crate) fn read_u8(&mut self, n: u32) -> Result<u8, String> {
+        debug_assert!(n <= 8);
+        Ok(self.read_bits(n)? as u8)
+    }
+
+    pub(crate) fn skip_bits(&mut self, n: usize) -> Result<(), String> {
+        let end = self.pos + n;
+        if end > self.data.len() * 8 {
+            return Err(format!(
+                "unexpected end of data at bit {}",
+                self.data.len() * 8
+            ));
+        }
+        self.pos = end;
+        Ok(())
+    }
+
+    /// Reads an unsigned Exp-Golomb-coded value (`ue(v)`).
+    pub(crate) fn read_ue(&mut self) -> Result<u32, String> {
+        let mut leading_zeros = 0;
+        while !self.read_bit()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return Err(format!("Exp-Golomb value too long at bit {}", self.pos));
+            }
+        }
+        let v = (1u64 << leading_zeros) - 1 + u64::from(self.read_bits(leading_zeros)?);
+        u32::try_from(v).map_err(|_| format!("Exp-Golomb value {} out of range", v))
+    }
+
+    /// Reads a signed Exp-Golomb-coded value (`se(v)`).
+    pub(crate) fn read_se(&mut self) -> Result<i32, String> {
+        let k = i64::from(self.read_ue()?);
+        let v = if k % 2 == 1 { (k + 1) / 2 } else { -(k / 2) };
+        Ok(v as i32)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testutil {
+    /// Packs a string of `0`s and `1`s, padded with zeros, after a NAL header,
+    /// adding emulation prevention bytes. Whitespace is ignored.
+    pub(crate) fn pack(header: &[u8], bits: &str) -> Vec<u8> {
+        let bits: Vec<u8> = bits.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        let mut nal = header.to_vec();
+        let mut zeros = 0;
+        for chunk in bits.chunks(8) {
+            let byte = chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (i, &b)| byte | (u8::from(b == b'1') << (7 - i)));
+            if zeros >= 2 && byte <= 3 {
+                nal.push(3);
+                zeros = 0;
+            }
+            zeros = if byte == 0 { zeros + 1 } else { 0 };
+            nal.push(byte);
+        }
+        nal
+    }
+
+    /// Returns the Exp-Golomb code for `v`.
+    pub(crate) fn ue(v: u32) -> String {
+        let code = format!("{:b}", u64::from(v) + 1);
+        format!("{}{}", "0".repeat(code.len() - 1), code)
+    }
+}
@@ -0,0 +1,251 @@
+//! H.264 RTP payloads, as in [RFC 6184](https://datatracker.ietf.org/doc/html/rfc6184).
+
+use bytes::Bytes;
+
+use super::{AccessUnit, Assembler, NalFormat, NalTypes, Unit};
+use crate::{client::rtp::RtpPacket, codec::h264::Parameters};
+
+const NAL_IDR: u8 = 5;
+const NAL_SPS: u8 = 7;
+const NAL_PPS: u8 = 8;
+const NAL_AUD: u8 = 9;
+const STAP_A: u8 = 24;
+const FU_A: u8 = 28;
+
+const NAL_TYPES: NalTypes = NalTypes {
+    nal_type,
+    aud: NAL_AUD,
+    parameter_sets: &[NAL_SPS, NAL_PPS],
+    is_keyframe: is_idr,
+};
+
+fn nal_type(nal: &[u8]) -> Option<u8> {
+    nal.first().map(|h| h & 0x1f)
+}
+
+fn is_idr(nal_type: u8) -> bool {
+    nal_type == NAL_IDR
+}
+
+/// Reassembles access units from single NAL unit, STAP-A and FU-A packets
+/// (`packetization-mode` 0 or 1).
+pub struct Depacketizer {
+    assembler: Assembler,
+}
+
+impl Depacketizer {
+    pub fn new(params: &Parameters, format: NalFormat) -> Result<Self, String> {
+        if params.packetization_mode == 2 {
+            return Err("interleaved mode (packetization-mode=2) isn't supported".to_owned());
+        }
+        let parameter_sets = vec![params.sps_nal.clone(), params.pps_nal.clone()];
+        Ok(Depacketizer {
+            assembler: Assembler::new(format, &NAL_TYPES, parameter_sets, 0),
+        })
+    }
+
+    pub fn push(&mut self, pkt: &RtpPacket) -> Result<(), String> {
+        self.assembler.push(pkt, push_payload)
+    }
+
+    pub fn pull(&mut self) -> Option<AccessUnit> {
+        self.assembler.pull()
+    }
+}
+
+/// Adds the NAL units of one RTP payload to `unit`.
+fn push_payload(unit: &mut Unit, payload: Bytes) -> Result<(), String> {
+    let header = *payload.first().ok_or_else(|| "empty payload".to_owned())?;
+    if header & 0x80 != 0 {
+        return Err("forbidden_zero_bit is set".to_owned());
+    }
+    match header & 0x1f {
+        1..=23 => unit.push(0, payload),
+        STAP_A => {
+            let mut pos = 1;
+            while pos < payload.len() {
+                let len = payload
+                    .get(pos..pos + 2)
+                    .map(|l| usize::from(u16::from_be_bytes([l[0], l[1]])))
+                    .ok_or_else(|| format!("truncated STAP-A size at {}", pos))?;
+                if len == 0 || pos + 2 + len > payload.len() {
+                    return Err(format!("bad STAP-A NAL unit size {} at {}", len, pos));
+                }
+                unit.push(0, payload.slice(pos + 2..pos + 2 + len));
+                pos += 2 + len;
+            }
+        }
+        FU_A => {
+            if payload.len() < 3 {
+                return Err(format!("{}-byte FU-A is too short", payload.len()));
+            }
+            let fu_header = payload[1];
+            let start = fu_header & 0x80 != 0;
+            let end = fu_header & 0x40 != 0;
+            let nal_header = [(header & 0xe0) | (fu_header & 0x1f)];
+            unit.fragment(start.then_some((0, &nal_header[..])), &payload[2..], end);
+        }
+        t => return Err(format!("unsupported packet type {}", t)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testutil::{annex_b, rtp};
+    use super::*;
+
+    const SPS: &[u8] = &[0x67, 0x4d, 0x00, 0x2a];
+    const PPS: &[u8] = &[0x68, 0xee, 0x3c, 0x80];
+    const IDR: &[u8] = &[0x65, 0x88, 0x80, 0x10, 0x00];
+    const SLICE: &[u8] = &[0x41, 0x9a, 0x02, 0x04];
+
+    fn depacketizer(format: NalFormat) -> Depacketizer {
+        let params = Parameters {
+            profile_level_id: None,
+            packetization_mode: 1,
+            sps: None,
+            pps: None,
+            sps_nal: Some(Bytes::from_static(SPS)),
+            pps_nal: Some(Bytes::from_static(PPS)),
+        };
+        Depacketizer::new(&params, format).unwrap()
+    }
+
+    fn pull_all(d: &mut Depacketizer) -> Vec<AccessUnit> {
+        std::iter::from_fn(|| d.pull()).collect()
+    }
+
+    #[test]
+    fn single_nal() {
+        let mut d = depacketizer(NalFormat::AnnexB);
+        d.push(&rtp(1, 1000, true, SLICE)).unwrap();
+        let aus = pull_all(&mut d);
+        assert_eq!(aus.len(), 1);
+        assert_eq!(aus[0].timestamp, 1000);
+        assert!(!aus[0].keyframe);
+        assert!(aus[0].complete);
+        assert_eq!(&aus[0].data[..], annex_b(&[SLICE]));
+
+        let mut d = depacketizer(NalFormat::Avcc);
+        d.push(&rtp(1, 1000, true, SLICE)).unwrap();
+        assert_eq!(&d.pull().unwrap().data[..], [&[0, 0, 0, 4], SLICE].concat());
+    }
+
+    #[test]
+    fn stap_a() {
+        let mut d = depacketizer(NalFormat::AnnexB);
+        let aud = [0x09, 0xf0];
+        let mut payload = vec![0x78];
+        for nal in [&aud[..], SPS, PPS, IDR] {
+            payload.extend_from_slice(&u16::try_from(nal.len()).unwrap().to_be_bytes());
+            payload.extend_from_slice(nal);
+        }
+        d.push(&rtp(1, 1000, true, &payload)).unwrap();
+        let au = d.pull().unwrap();
+        assert!(au.keyframe);
+        assert!(au.complete);
+        assert_eq!(&au.data[..], annex_b(&[&aud, SPS, PPS, IDR]));
+
+        // A size running past the end of the payload.
+        d.push(&rtp(2, 2000, true, &[0x78, 0x00, 0x05, 0x41, 0x9a]))
+            .unwrap_err();
+    }
+
+    #[test]
+    fn fu_a_prepends_parameter_sets() {
+        let mut d = depacketizer(NalFormat::AnnexB);
+        d.push(&rtp(1, 1000, false, &[0x7c, 0x85, 0x88, 0x80]))
+            .unwrap();
+        d.push(&rtp(2, 1000, false, &[0x7c, 0x05, 0x10])).unwrap();
+        d.push(&rtp(3, 1000, true, &[0x7c, 0x45, 0x00])).unwrap();
+        let au = d.pull().unwrap();
+        assert!(au.keyframe);
+        assert!(au.complete);
+        assert_eq!(&au.data[..], annex_b(&[SPS, PPS, IDR]));
+    }
+
+    #[test]
+    fn in_band_parameter_sets_replace_sprop() {
+        let mut d = depacketizer(NalFormat::AnnexB);
+        let sps = [0x67, 0x64, 0x00, 0x28];
+        d.push(&rtp(1, 1000, false, &sps)).unwrap();
+        d.push(&rtp(2, 1000, true, IDR)).unwrap();
+        d.push(&rtp(3, 2000, true, IDR)).unwrap();
+        let aus = pull_all(&mut d);
+        assert_eq!(&aus[0].data[..], annex_b(&[&sps, PPS, IDR]));
+        assert_eq!(&aus[1].data[..], annex_b(&[&sps, PPS, IDR]));
+    }
+
+    #[test]
+    fn fu_a_start_lost() {
+        let mut d = depacketizer(NalFormat::AnnexB);
+        d.push(&rtp(1, 1000, false, SLICE)).unwrap();
+        // Sequence number 2, the FU-A start, is lost.
+        d.push(&rtp(3, 1000, false, &[0x7c, 0x05, 0x10])).unwrap();
+        d.push(&rtp(4, 1000, true, &[0x7c, 0x45, 0x00])).unwrap();
+        d.push(&rtp(5, 2000, true, SLICE)).unwrap();
+        let aus = pull_all(&mut d);
+        assert_eq!(aus.len(), 2);
+        assert!(!aus[0].complete);
+        assert!(!aus[0].keyframe);
+        assert_eq!(&aus[0].data[..], annex_b(&[SLICE]));
+        assert!(aus[1].complete);
+    }
+
+    #[test]
+    fn fu_a_end_lost() {
+        let mut d = depacketizer(NalFormat::AnnexB);
+        d.push(&rtp(1, 1000, false, &[0x7c, 0x85, 0x88, 0x80]))
+            .unwrap();
+        // Sequence number 2, the FU-A end with the marker bit, is lost.
+        d.push(&rtp(3, 2000, true, SLICE)).unwrap();
+        let aus = pull_all(&mut d);
+        assert_eq!(aus.len(), 1);
+        assert_eq!(aus[0].timestamp, 2000);
+        assert!(!aus[0].complete);
+        assert_eq!(&aus[0].data[..], annex_b(&[SLICE]));
+    }
+
+    #[test]
+    fn missing_marker() {
+        let mut d = depacketizer(NalFormat::AnnexB);
+        d.push(&rtp(1, 1000, false, SLICE)).unwrap();
+        d.push(&rtp(2, 2000, true, SLICE)).unwrap();
+        let aus = pull_all(&mut d);
+        assert_eq!(
+            aus.iter().map(|au| au.timestamp).collect::<Vec<_>>(),
+            [1000, 2000]
+        );
+        assert!(aus.iter().all(|au| au.complete));
+    }
+
+    #[test]
+    fn missing_marker_and_sequence_gap() {
+        // Packet 2 may have been the end of 1000 or the start of 2000.
+        let mut d = depacketizer(NalFormat::AnnexB);
+        d.push(&rtp(1, 1000, false, SLICE)).unwrap();
+        d.push(&rtp(3, 2000, true, SLICE)).unwrap();
+        d.push(&rtp(4, 3000, true, SLICE)).unwrap();
+        let aus = pull_all(&mut d);
+        assert_eq!(
+            aus.iter()
+                .map(|au| (au.timestamp, au.complete))
+                .collect::<Vec<_>>(),
+            [(1000, false), (2000, false), (3000, true)]
+        );
+    }
+
+    #[test]
+    fn interleaved_mode_rejected() {
+        let params = Parameters {
+            profile_level_id: None,
+            packetization_mode: 2,
+            sps: None,
+            pps: None,
+            sps_nal: None,
+            pps_nal: None,
+        };
+        assert!(Depacketizer::new(&params, NalFormat::AnnexB).is_err());
+    }
+}
@@ -0,0 +1,384 @@
+//! H.265 RTP payloads, as in [RFC 7798](https://datatracker.ietf.org/doc/html/rfc7798).
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use super::{AccessUnit, Assembler, NalFormat, NalTypes, Unit};
+use crate::{
+    client::rtp::RtpPacket,
+    codec::h265::{nal_type, Parameters},
+};
+
+const NAL_VPS: u8 = 32;
+const NAL_SPS: u8 = 33;
+const NAL_PPS: u8 = 34;
+const NAL_AUD: u8 = 35;
+const AP: u8 = 48;
+const FU: u8 = 49;
+const PACI: u8 = 50;
+
+const NAL_TYPES: NalTypes = NalTypes {
+    nal_type,
+    aud: NAL_AUD,
+    parameter_sets: &[NAL_VPS, NAL_SPS, NAL_PPS],
+    is_keyframe: is_irap,
+};
+
+/// Returns true for the NAL unit types of intra random access point pictures.
+fn is_irap(nal_type: u8) -> bool {
+    (16..=23).contains(&nal_type)
+}
+
+/// Reassembles access units from single NAL unit, aggregation (AP),
+/// fragmentation (FU) and PACI packets.
+///
+/// When `sprop-max-don-diff` is non-zero, NAL units may be interleaved across
+/// access units. They're released in decoding order once no later packet can
+/// precede them, so each access unit is emitted only after some NAL units of
+/// the following ones arrive.
+pub struct Depacketizer {
+    /// Payloads carry decoding order numbers, as `sprop-max-don-diff` is non-zero.
+    has_don: bool,
+    assembler: Assembler,
+}
+
+impl Depacketizer {
+    pub fn new(params: &Parameters, format: NalFormat) -> Result<Self, String> {
+        let parameter_sets = vec![
+            params.vps_nal.clone(),
+            params.sps_nal.clone(),
+            params.pps_nal.clone(),
+        ];
+        Ok(Depacketizer {
+            has_don: params.max_don_diff > 0,
+            assembler: Assembler::new(format, &NAL_TYPES, parameter_sets, params.max_don_diff),
+        })
+    }
+
+    pub fn push(&mut self, pkt: &RtpPacket) -> Result<(), String> {
+        let has_don = self.has_don;
+        self.assembler.push(pkt, |unit, payload| {
+            push_payload(unit, payload, has_don, true)
+        })
+    }
+
+    pub fn pull(&mut self) -> Option<AccessUnit> {
+        self.assembler.pull()
+    }
+}
+
+/// Adds the NAL units of one RTP payload to `unit`; `allow_paci` is false
+/// within a PACI packet, which can't nest.
+fn push_payload(
+    unit: &mut Unit,
+    payload: Bytes,
+    has_don: bool,
+    allow_paci: bool,
+) -> Result<(), String> {
+    if payload.len() < 3 {
+        return Err(format!("{}-byte payload is too short", payload.len()));
+    }
+    if payload[0] & 0x80 != 0 {
+        return Err("forbidden_zero_bit is set".to_owned());
+    }
+    let don_len = if has_don { 2 } else { 0 };
+    match (payload[0] >> 1) & 0x3f {
+        t @ 0..=47 => {
+            if !has_don {
+                unit.push(0, payload);
+                return Ok(());
+            }
+            if payload.len() < 5 {
+                return Err(format!(
+                    "{}-byte NAL unit of type {} is too short for DONL",
+                    payload.len(),
+                    t
+                ));
+            }
+            let mut nal = BytesMut::with_capacity(payload.len() - 2);
+            nal.put_slice(&payload[..2]);
+            nal.put_slice(&payload[4..]);
+            unit.push(read_u16(&payload, 2), nal.freeze());
+        }
+        AP => {
+            let mut pos = 2;
+            let mut don = 0;
+            let mut first = true;
+            while pos < payload.len() {
+                if has_don {
+                    if first {
+                        don = payload
+                            .get(pos..pos + 2)
+                            .map(|d| read_u16(d, 0))
+                            .ok_or_else(|| format!("truncated AP DONL at {}", pos))?;
+                    } else {
+                        let dond = *payload
+                            .get(pos)
+                            .ok_or_else(|| format!("truncated AP DOND at {}", pos))?;
+                        don = don.wrapping_add(u16::from(dond) + 1);
+                    }
+                    pos += if first { 2 } else { 1 };
+                }
+                first = false;
+                let len = payload
+                    .get(pos..pos + 2)
+                    .map(|l| usize::from(read_u16(l, 0)))
+                    .ok_or_else(|| format!("truncated AP size at {}", pos))?;
+                if len < 2 || pos + 2 + len > payload.len() {
+                    return Err(format!("bad AP NAL unit size {} at {}", len, pos));
+                }
+                unit.push(don, payload.slice(pos + 2..pos + 2 + len));
+                pos += 2 + len;
+            }
+        }
+        FU => {
+            let fu_header = payload[2];
+            let start = fu_header & 0x80 != 0;
+            let end = fu_header & 0x40 != 0;
+            if !start {
+                unit.fragment(None, &payload[3..], end);
+                return Ok(());
+            }
+            if payload.len() <= 3 + don_len {
+                return Err(format!("{}-byte FU is too short", payload.len()));
+            }
+            let don = if has_don { read_u16(&payload, 3) } else { 0 };
+            let nal_header = [(payload[0] & 0x81) | ((fu_header & 0x3f) << 1), payload[1]];
+            unit.fragment(Some((don, &nal_header[..])), &payload[3 + don_len..], end);
+        }
+        PACI if allow_paci => {
+            if payload.len() < 4 {
+                return Err(format!("{}-byte PACI is too short", payload.len()));
+            }
+            let c_type = (payload[2] >> 1) & 0x3f;
+            let phs_size = usize::from((payload[2] & 0x01) << 4 | payload[3] >> 4);
+            let start = 4 + phs_size;
+            if start + 2 > payload.len() {
+                return Err(format!(
+                    "{}-byte PACI too short for {}-byte header extension",
+                    payload.len(),
+                    phs_size
+                ));
+            }
+
+            // The inner payload's header is the PACI's, with the A bit as
+            // F and cType as its type.
+            let mut inner = BytesMut::with_capacity(payload.len() - start + 2);
+            inner.put_u8((payload[2] & 0x80) | (c_type << 1) | (payload[0] & 0x01));
+            inner.put_u8(payload[1]);
+            inner.put_slice(&payload[start..]);
+            return push_payload(unit, inner.freeze(), has_don, false);
+        }
+        t => return Err(format!("unsupported packet type {}", t)),
+    }
+    Ok(())
+}
+
+fn read_u16(b: &[u8], pos: usize) -> u16 {
+    u16::from_be_bytes([b[pos], b[pos + 1]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testutil::{annex_b, rtp};
+    use super::*;
+
+    const VPS: &[u8] = &[0x40, 0x01, 0x0c, 0x01];
+    const SPS: &[u8] = &[0x42, 0x01, 0x01, 0x01];
+    const PPS: &[u8] = &[0x44, 0x01, 0xc0, 0xf2];
+    const AUD: &[u8] = &[0x46, 0x01, 0x50];
+    const IDR: &[u8] = &[0x26, 0x01, 0xaf, 0x08, 0x40];
+    const TRAIL: [&[u8]; 3] = [
+        &[0x02, 0x01, 0xd0, 0x01],
+        &[0x02, 0x01, 0xd0, 0x02],
+        &[0x02, 0x01, 0xd0, 0x03],
+    ];
+
+    fn depacketizer(max_don_diff: u16) -> Depacketizer {
+        let params = Parameters {
+            vps: None,
+            sps: None,
+            pps: None,
+            max_don_diff,
+            vps_nal: Some(Bytes::from_static(VPS)),
+            sps_nal: Some(Bytes::from_static(SPS)),
+            pps_nal: Some(Bytes::from_static(PPS)),
+        };
+        Depacketizer::new(&params, NalFormat::AnnexB).unwrap()
+    }
+
+    /// Returns an aggregation packet of `nals`, without decoding order numbers.
+    fn ap(nals: &[&[u8]]) -> Vec<u8> {
+        let mut payload = vec![0x60, 0x01];
+        for nal in nals {
+            payload.extend_from_slice(&u16::try_from(nal.len()).unwrap().to_be_bytes());
+            payload.extend_from_slice(nal);
+        }
+        payload
+    }
+
+    fn pull_all(d: &mut Depacketizer) -> Vec<AccessUnit> {
+        std::iter::from_fn(|| d.pull()).collect()
+    }
+
+    #[test]
+    fn single_nal_prepends_parameter_sets() {
+        let mut d = depacketizer(0);
+        d.push(&rtp(1, 1000, true, IDR)).unwrap();
+        d.push(&rtp(2, 2000, true, TRAIL[0])).unwrap();
+        let aus = pull_all(&mut d);
+        assert!(aus[0].keyframe);
+        assert!(aus[0].complete);
+        assert_eq!(&aus[0].data[..], annex_b(&[VPS, SPS, PPS, IDR]));
+        assert!(!aus[1].keyframe);
+        assert_eq!(&aus[1].data[..], annex_b(&[TRAIL[0]]));
+    }
+
+    #[test]
+    fn aggregation() {
+        let mut d = depacketizer(0);
+        d.push(&rtp(1, 1000, true, &ap(&[AUD, VPS, SPS, PPS, IDR])))
+            .unwrap();
+
+        // Only the SPS is in-band; the others are inserted around it.
+        let sps = [0x42, 0x01, 0x01, 0x02];
+        d.push(&rtp(2, 2000, true, &ap(&[AUD, &sps, IDR]))).unwrap();
+        let aus = pull_all(&mut d);
+        assert_eq!(&aus[0].data[..], annex_b(&[AUD, VPS, SPS, PPS, IDR]));
+        assert_eq!(&aus[1].data[..], annex_b(&[AUD, VPS, &sps, PPS, IDR]));
+
+        d.push(&rtp(3, 3000, true, &[0x60, 0x01, 0x00, 0x09, 0x02]))
+            .unwrap_err();
+    }
+
+    #[test]
+    fn fragmentation() {
+        let mut d = depacketizer(0);
+        d.push(&rtp(1, 1000, false, &[0x62, 0x01, 0x93, 0xaf]))
+            .unwrap();
+        d.push(&rtp(2, 1000, false, &[0x62, 0x01, 0x13, 0x08]))
+            .unwrap();
+        d.push(&rtp(3, 1000, true, &[0x62, 0x01, 0x53, 0x40]))
+            .unwrap();
+        let au = d.pull().unwrap();
+        assert!(au.keyframe);
+        assert!(au.complete);
+        assert_eq!(&au.data[..], annex_b(&[VPS, SPS, PPS, IDR]));
+
+        // The start is lost, leaving nothing of the access unit.
+        d.push(&rtp(5, 2000, false, &[0x62, 0x01, 0x01, 0xd0]))
+            .unwrap();
+        d.push(&rtp(6, 2000, true, &[0x62, 0x01, 0x41, 0x01]))
+            .unwrap();
+        d.push(&rtp(7, 3000, true, TRAIL[0])).unwrap();
+        let aus = pull_all(&mut d);
+        assert_eq!(aus.len(), 1);
+        assert_eq!(aus[0].timestamp, 3000);
+        assert!(aus[0].complete);
+    }
+
+    #[test]
+    fn paci() {
+        let mut d = depacketizer(0);
+        // A PACI with a one-byte header extension, carrying an IDR NAL unit.
+        d.push(&rtp(
+            1,
+            1000,
+            true,
+            &[0x64, 0x01, 0x26, 0x10, 0xff, 0xaf, 0x08, 0x40],
+        ))
+        .unwrap();
+        assert_eq!(&d.pull().unwrap().data[..], annex_b(&[VPS, SPS, PPS, IDR]));
+
+        // A PACI carrying a FU.
+        d.push(&rtp(2, 2000, false, &[0x64, 0x01, 0x62, 0x00, 0x81, 0xd0]))
+            .unwrap();
+        d.push(&rtp(3, 2000, true, &[0x62, 0x01, 0x41, 0x01]))
+            .unwrap();
+        assert_eq!(&d.pull().unwrap().data[..], annex_b(&[TRAIL[0]]));
+
+        // PACIs can't nest.
+        d.push(&rtp(4, 3000, true, &[0x64, 0x01, 0x64, 0x00, 0x02, 0x01]))
+            .unwrap_err();
+    }
+
+    /// Returns a single NAL unit packet of `nal` with decoding order number `don`.
+    fn donl(don: u16, nal: &[u8]) -> Vec<u8> {
+        let mut payload = nal[..2].to_vec();
+        payload.extend_from_slice(&don.to_be_bytes());
+        payload.extend_from_slice(&nal[2..]);
+        payload
+    }
+
+    #[test]
+    fn decoding_order() {
+        let mut d = depacketizer(2);
+
+        // A single NAL unit with DON 0, after an AP with DONs 65534 and 65535.
+        d.push(&rtp(1, 1000, false, &donl(0, TRAIL[2]))).unwrap();
+        let mut aggregation = vec![0x60, 0x01, 0xff, 0xfe];
+        aggregation.extend_from_slice(&[0x00, 0x04]);
+        aggregation.extend_from_slice(TRAIL[0]);
+        aggregation.push(0x00);
+        aggregation.extend_from_slice(&[0x00, 0x04]);
+        aggregation.extend_from_slice(TRAIL[1]);
+        d.push(&rtp(2, 1000, true, &aggregation)).unwrap();
+
+        // A FU carries its DON in the start fragment only.
+        d.push(&rtp(3, 2000, false, &[0x62, 0x01, 0x81, 0x00, 0x01, 0xd0]))
+            .unwrap();
+        d.push(&rtp(4, 2000, true, &[0x62, 0x01, 0x41, 0x01]))
+            .unwrap();
+        assert!(d.pull().is_none(), "DON 1 may still be followed by DON 0");
+
+        // DON 5 releases everything up to DON 2.
+        d.push(&rtp(5, 3000, true, &donl(2, TRAIL[0]))).unwrap();
+        d.push(&rtp(6, 4000, true, &donl(5, TRAIL[1]))).unwrap();
+        let aus = pull_all(&mut d);
+        assert_eq!(aus.len(), 2);
+        assert_eq!(&aus[0].data[..], annex_b(&TRAIL));
+        assert_eq!(aus[1].timestamp, 2000);
+        assert_eq!(&aus[1].data[..], annex_b(&[TRAIL[0]]));
+
+        // Too short for DONL.
+        d.push(&rtp(7, 5000, true, &[0x02, 0x01, 0x00, 0x01]))
+            .unwrap_err();
+    }
+
+    #[test]
+    fn interleaved_access_units() {
+        let mut d = depacketizer(1);
+        let nal = |i: u8| [0x02, 0x01, 0xd0, i];
+
+        // Access units 1000 and 2000 are sent alternately, each NAL unit at
+        // most one behind the highest DON before it.
+        let packets: [(u32, u16, u8); 6] = [
+            (1000, 0, 0x10),
+            (2000, 2, 0x20),
+            (1000, 1, 0x11),
+            (2000, 3, 0x21),
+            (3000, 4, 0x30),
+            (4000, 6, 0x40),
+        ];
+        for (seq, &(ts, don, i)) in (1..).zip(&packets) {
+            d.push(&rtp(seq, ts, true, &donl(don, &nal(i)))).unwrap();
+        }
+        let aus = pull_all(&mut d);
+        assert_eq!(aus.len(), 2);
+        assert_eq!(aus[0].timestamp, 1000);
+        assert_eq!(&aus[0].data[..], annex_b(&[&nal(0x10), &nal(0x11)]));
+        assert_eq!(aus[1].timestamp, 2000);
+        assert_eq!(&aus[1].data[..], annex_b(&[&nal(0x20), &nal(0x21)]));
+        assert!(aus.iter().all(|au| au.complete));
+
+        // A lost packet may have belonged to any access unit not yet emitted:
+        // 3000 and 4000, still buffered, or 5000, the next to arrive.
+        d.push(&rtp(8, 5000, true, &donl(7, &nal(0x50)))).unwrap();
+        d.push(&rtp(9, 6000, true, &donl(9, &nal(0x60)))).unwrap();
+        d.push(&rtp(10, 7000, true, &donl(11, &nal(0x70)))).unwrap();
+        d.push(&rtp(11, 8000, true, &donl(13, &nal(0x80)))).unwrap();
+        let aus = pull_all(&mut d);
+        let complete: Vec<_> = aus.iter().map(|au| (au.timestamp, au.complete)).collect();
+        let expected = [(3000, false), (4000, false), (5000, false), (6000, true)];
+        assert_eq!(complete, expected);
+    }
+}
@@ -0,0 +1,466 @@
+//! Reassembling video access units from RTP packets.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use super::Parameters;
+use crate::client::rtp::RtpPacket;
+
+pub mod h264;
+pub mod h265;
+
+/// How NAL units are delimited within an [`AccessUnit`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NalFormat {
+    /// Each NAL unit is preceded by a `00 00 00 01` start code, as in ISO/IEC
+    /// 14496-10 Annex B. Such a stream can be written straight to a `.h264` file.
+    AnnexB,
+
+    /// Each NAL unit is preceded by its length as a 4-byte big-endian integer,
+    /// as in ISO/IEC 14496-15 (`avcC`/`hvcC`) sample data.
+    Avcc,
+}
+
+impl NalFormat {
+    fn push(self, out: &mut BytesMut, nal: &[u8]) {
+        match self {
+            NalFormat::AnnexB => out.put_slice(&[0, 0, 0, 1]),
+            NalFormat::Avcc => {
+                out.put_u32(u32::try_from(nal.len()).expect("NAL unit fits in u32"))
+            }
+        }
+        out.put_slice(nal);
+    }
+}
+
+/// The NAL units of one picture, with their delimiters.
+#[derive(Clone, Debug)]
+pub struct AccessUnit {
+    /// The RTP timestamp shared by the access unit's packets.
+    pub timestamp: u32,
+
+    /// True if the access unit can be decoded on its own: an IDR picture for
+    /// H.264, an IRAP picture for H.265. Parameter sets are included.
+    pub keyframe: bool,
+
+    /// False if packets were lost while assembling the access unit, so it's
+    /// likely undecodable.
+    pub complete: bool,
+    pub data: Bytes,
+}
+
+/// A depacketizer for any supported video codec.
+pub enum Depacketizer {
+    H264(h264::Depacketizer),
+    H265(h265::Depacketizer),
+}
+
+impl Depacketizer {
+    /// Creates a depacketizer for a stream with the given parameters, or returns
+    /// `None` if its codec isn't supported.
+    pub fn new(params: &Parameters, format: NalFormat) -> Option<Result<Self, String>> {
+        match params {
+            Parameters::H264(p) => {
+                Some(h264::Depacketizer::new(p, format).map(Depacketizer::H264))
+            }
+            Parameters::H265(p) => {
+                Some(h265::Depacketizer::new(p, format).map(Depacketizer::H265))
+            }
+            _ => None,
+        }
+    }
+
+    /// Adds the next RTP packet of the stream, which must be fed in order of
+    /// arrival.
+    pub fn push(&mut self, pkt: &RtpPacket) -> Result<(), String> {
+        match self {
+            Depacketizer::H264(d) => d.push(pkt),
+            Depacketizer::H265(d) => d.push(pkt),
+        }
+    }
+
+    /// Returns the next completed access unit, if any.
+    pub fn pull(&mut self) -> Option<AccessUnit> {
+        match self {
+            Depacketizer::H264(d) => d.pull(),
+            Depacketizer::H265(d) => d.pull(),
+        }
+    }
+}
+
+/// What [`Assembler`] needs to know of a codec's NAL unit types.
+struct NalTypes {
+    /// Returns the type from a NAL unit's header, if it's long enough to have one.
+    nal_type: fn(&[u8]) -> Option<u8>,
+
+    /// The access unit delimiter type.
+    aud: u8,
+
+    /// The parameter set types, in the order they must appear.
+    parameter_sets: &'static [u8],
+
+    /// Returns true for the types of NAL units which make an access unit a keyframe.
+    is_keyframe: fn(u8) -> bool,
+}
+
+/// Groups NAL units into access units by RTP timestamp, tracking loss, for
+/// either codec.
+///
+/// When NAL units carry decoding order numbers, they may be interleaved across
+/// access units, so they're first put back in decoding order by a [`Reorder`]
+/// buffer. Access units then end at a change of timestamp, not a marker bit.
+struct Assembler {
+    format: NalFormat,
+    types: &'static NalTypes,
+
+    /// The latest of each of `types.parameter_sets`, from the SDP or in-band,
+    /// to prepend to keyframes which lack them.
+    parameter_sets: Vec<Option<Bytes>>,
+
+    current: Option<Pending>,
+
+    /// A NAL unit being reassembled from fragments, header included, and its
+    /// decoding order number.
+    fu: Option<(u16, BytesMut)>,
+    last_seq: Option<u16>,
+    reorder: Option<Reorder>,
+    ready: VecDeque<AccessUnit>,
+}
+
+/// NAL units awaiting release in decoding order.
+///
+/// A NAL unit can arrive at most `sprop-max-don-diff` behind the highest
+/// decoding order number seen before it, so one further behind than that is
+/// released.
+struct Reorder {
+    max_don_diff: i64,
+
+    /// By absolute decoding order number, then order of arrival: the RTP
+    /// timestamp, the NAL unit and whether its access unit is still complete.
+    buffer: BTreeMap<(i64, u64), (u32, Bytes, bool)>,
+
+    /// The decoding order number and absolute one of the last NAL unit added.
+    last: Option<(u16, i64)>,
+    highest: i64,
+    added: u64,
+}
+
+/// An access unit being assembled.
+struct Pending {
+    timestamp: u32,
+
+    /// NAL units with their decoding order numbers, which are zero unless the
+    /// payloads carry them.
+    nals: Vec<(u16, Bytes)>,
+    complete: bool,
+}
+
+/// The state needed to add one RTP payload's NAL units to the access unit
+/// being assembled.
+struct Unit<'a> {
+    pending: &'a mut Pending,
+    fu: &'a mut Option<(u16, BytesMut)>,
+}
+
+impl Assembler {
+    /// Creates an assembler; a non-zero `max_don_diff` reorders NAL units by
+    /// their decoding order numbers across access units.
+    fn new(
+        format: NalFormat,
+        types: &'static NalTypes,
+        parameter_sets: Vec<Option<Bytes>>,
+        max_don_diff: u16,
+    ) -> Self {
+        Assembler {
+            format,
+            types,
+            parameter_sets,
+            current: None,
+            fu: None,
+            last_seq: None,
+            reorder: (max_don_diff > 0).then(|| Reorder {
+                max_don_diff: i64::from(max_don_diff),
+                buffer: BTreeMap::new(),
+                last: None,
+                highest: i64::MIN,
+                added: 0,
+            }),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Adds `pkt`, passing its payload to `add` to split into NAL units.
+    fn push(
+        &mut self,
+        pkt: &RtpPacket,
+        add: impl FnOnce(&mut Unit, Bytes) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let seq = pkt.sequence_number();
+        let lost = matches!(self.last_seq, Some(last) if seq != last.wrapping_add(1));
+        self.last_seq = Some(seq);
+        if self.reorder.is_some() {
+            return self.push_interleaved(pkt, lost, add);
+        }
+        if let Some(p) = &mut self.current {
+            if p.timestamp != pkt.timestamp() {
+                // The previous access unit's last packet, with the marker bit,
+                // was lost, perhaps along with others of its packets.
+                if lost {
+                    p.complete = false;
+                }
+                self.finish();
+            }
+        }
+        if lost {
+            self.fu = None;
+        }
+        let pending = self.current.get_or_insert_with(|| Pending {
+            timestamp: pkt.timestamp(),
+            nals: Vec::new(),
+            complete: true,
+        });
+        if lost {
+            pending.complete = false;
+        }
+        let mut unit = Unit {
+            pending,
+            fu: &mut self.fu,
+        };
+        let result = add(&mut unit, pkt.payload_bytes());
+        if result.is_err() {
+            unit.pending.complete = false;
+        }
+        if pkt.mark() {
+            self.finish();
+        }
+        result
+    }
+
+    /// As [`Assembler::push`], for NAL units with decoding order numbers.
+    fn push_interleaved(
+        &mut self,
+        pkt: &RtpPacket,
+        lost: bool,
+        add: impl FnOnce(&mut Unit, Bytes) -> Result<(), String>,
+    ) -> Result<(), String> {
+        if lost {
+            self.fu = None;
+        }
+        let mut pending = Pending {
+            timestamp: pkt.timestamp(),
+            nals: Vec::new(),
+            complete: !lost,
+        };
+        let mut unit = Unit {
+            pending: &mut pending,
+            fu: &mut self.fu,
+        };
+        let result = add(&mut unit, pkt.payload_bytes());
+        if result.is_err() {
+            pending.complete = false;
+        }
+
+        // Whatever was lost may belong to any access unit not yet emitted.
+        if !pending.complete {
+            if let Some(p) = &mut self.current {
+                p.complete = false;
+            }
+        }
+        let reorder = self.reorder.as_mut().expect("interleaved");
+        reorder.add(pending.timestamp, pending.nals, pending.complete);
+        while let Some((timestamp, nal, complete)) = self.reorder.as_mut().and_then(Reorder::pop) {
+            if let Some(p) = self.current.take_if(|p| p.timestamp != timestamp) {
+                self.emit(p);
+            }
+            let p = self.current.get_or_insert_with(|| Pending {
+                timestamp,
+                nals: Vec::new(),
+                complete: true,
+            });
+            p.complete &= complete;
+
+            // Already in decoding order.
+            p.nals.push((0, nal));
+        }
+        result
+    }
+
+    fn pull(&mut self) -> Option<AccessUnit> {
+        self.ready.pop_front()
+    }
+
+    fn finish(&mut self) {
+        let mut pending = match self.current.take() {
+            Some(p) => p,
+            None => return,
+        };
+        if self.fu.take().is_some() {
+            pending.complete = false;
+        }
+        self.emit(pending);
+    }
+
+    fn emit(&mut self, mut pending: Pending) {
+        let first_don = match pending.nals.first() {
+            Some(&(don, _)) => don,
+            None => return,
+        };
+
+        // Transmission order may differ from decoding order; the sort is
+        // stable, so it's a no-op without decoding order numbers.
+        pending
+            .nals
+            .sort_by_key(|&(don, _)| don.wrapping_sub(first_don) as i16);
+
+        let types = self.types;
+        let rank = |nal: &[u8]| {
+            (types.nal_type)(nal).and_then(|t| types.parameter_sets.iter().position(|&p| p == t))
+        };
+        let mut keyframe = false;
+        let mut present = vec![false; self.parameter_sets.len()];
+        for (_, nal) in &pending.nals {
+            if let Some(i) = rank(nal) {
+                present[i] = true;
+                self.parameter_sets[i] = Some(nal.clone());
+            } else if (types.nal_type)(nal).is_some_and(types.is_keyframe) {
+                keyframe = true;
+            }
+        }
+        let format = self.format;
+        let mut out = BytesMut::new();
+        let mut nals = pending.nals.iter().map(|(_, nal)| nal).peekable();
+
+        // An access unit delimiter, if present, must come first.
+        while let Some(aud) = nals.next_if(|n| (types.nal_type)(n) == Some(types.aud)) {
+            format.push(&mut out, aud);
+        }
+
+        // A keyframe's missing parameter sets go after any in-band ones which
+        // precede them and before everything else.
+        let mut missing = self
+            .parameter_sets
+            .iter()
+            .zip(present)
+            .enumerate()
+            .filter(|_| keyframe)
+            .filter_map(|(i, (nal, present))| Some((i, nal.as_ref().filter(|_| !present)?)))
+            .peekable();
+        for nal in nals {
+            let r = rank(nal);
+            while let Some((_, ps)) = missing.next_if(|&(m, _)| r.is_none_or(|r| m < r)) {
+                format.push(&mut out, ps);
+            }
+            format.push(&mut out, nal);
+        }
+        self.ready.push_back(AccessUnit {
+            timestamp: pending.timestamp,
+            keyframe,
+            complete: pending.complete,
+            data: out.freeze(),
+        });
+    }
+}
+
+impl Reorder {
+    /// Adds the NAL units of one packet, marking every NAL unit still buffered
+    /// incomplete if `complete` is false.
+    fn add(&mut self, timestamp: u32, nals: Vec<(u16, Bytes)>, complete: bool) {
+        if !complete {
+            for (_, _, c) in self.buffer.values_mut() {
+                *c = false;
+            }
+        }
+        for (don, nal) in nals {
+            let abs = match self.last {
+                Some((last_don, last_abs)) => {
+                    last_abs + i64::from(don.wrapping_sub(last_don) as i16)
+                }
+                None => i64::from(don),
+            };
+            self.last = Some((don, abs));
+            self.highest = self.highest.max(abs);
+            self.buffer
+                .insert((abs, self.added), (timestamp, nal, complete));
+            self.added += 1;
+        }
+    }
+
+    /// Removes the first NAL unit in decoding order, if no NAL unit still to
+    /// come can precede it.
+    fn pop(&mut self) -> Option<(u32, Bytes, bool)> {
+        let entry = self.buffer.first_entry()?;
+        if entry.key().0 >= self.highest - self.max_don_diff {
+            return None;
+        }
+        Some(entry.remove())
+    }
+}
+
+impl Unit<'_> {
+    /// Adds a whole NAL unit, abandoning any fragmented one.
+    fn push(&mut self, don: u16, nal: Bytes) {
+        if self.fu.take().is_some() {
+            self.pending.complete = false;
+        }
+        self.pending.nals.push((don, nal));
+    }
+
+    /// Adds a fragment of a NAL unit. The first fragment has `start` set to the
+    /// NAL unit's decoding order number and reconstructed header.
+    fn fragment(&mut self, start: Option<(u16, &[u8])>, data: &[u8], end: bool) {
+        match start {
+            Some((don, header)) => {
+                if self.fu.is_some() {
+                    self.pending.complete = false;
+                }
+                let mut nal = BytesMut::with_capacity(header.len() + data.len());
+                nal.put_slice(header);
+                nal.put_slice(data);
+                *self.fu = Some((don, nal));
+            }
+            None => match self.fu {
+                Some((_, nal)) => nal.put_slice(data),
+                None => {
+                    // The start was lost; drop the rest of this NAL unit.
+                    self.pending.complete = false;
+                    return;
+                }
+            },
+        }
+        if end {
+            if let Some((don, nal)) = self.fu.take() {
+                self.pending.nals.push((don, nal.freeze()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testutil {
+    use bytes::Bytes;
+
+    use crate::client::rtp::RtpPacket;
+
+    /// Returns a packet with the given header fields and payload.
+    pub(crate) fn rtp(
+        sequence_number: u16,
+        timestamp: u32,
+        mark: bool,
+        payload: &[u8],
+    ) -> RtpPacket {
+        let mut data = vec![0x80, if mark { 0x80 | 96 } else { 96 }];
+        data.extend_from_slice(&sequence_number.to_be_bytes());
+        data.extend_from_slice(&timestamp.to_be_bytes());
+        data.extend_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+        data.extend_from_slice(payload);
+        RtpPacket::parse(Bytes::from(data)).unwrap()
+    }
+
+    /// Returns `nals` with Annex B start codes.
+    pub(crate) fn annex_b(nals: &[&[u8]]) -> Vec<u8> {
+        nals.iter()
+            .flat_map(|nal| [&[0, 0, 0, 1][..], nal].concat())
+            .collect()
+    }
+}
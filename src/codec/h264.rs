@@ -0,0 +1,541 @@
+//! H.264 parameters, as described in
+//! [RFC 6184 section 8.1](https://datatracker.ietf.org/doc/html/rfc6184#section-8.1).
+
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+use serde::Serialize;
+
+use super::bits::{decode_rbsp, BitReader};
+
+const NAL_SPS: u8 = 7;
+const NAL_PPS: u8 = 8;
+
+/// Parameters from a H.264 media section's `a=fmtp` line.
+#[derive(Clone, Debug, Serialize)]
+pub struct Parameters {
+    pub profile_level_id: Option<ProfileLevelId>,
+    pub packetization_mode: u8,
+    pub sps: Option<Sps>,
+    pub pps: Option<Pps>,
+
+    /// The raw NAL units from `sprop-parameter-sets`, header byte included.
+    #[serde(skip)]
+    pub sps_nal: Option<Bytes>,
+    #[serde(skip)]
+    pub pps_nal: Option<Bytes>,
+}
+
+/// The `profile-level-id` parameter: the first three bytes of the SPS, hex-encoded.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct ProfileLevelId {
+    pub profile_idc: u8,
+    pub constraint_flags: u8,
+    pub level_idc: u8,
+}
+
+/// The fields of a sequence parameter set that characterize the stream.
+#[derive(Clone, Debug, Serialize)]
+pub struct Sps {
+    pub profile_idc: u8,
+    pub constraint_flags: u8,
+    pub level_idc: u8,
+    pub seq_parameter_set_id: u32,
+    pub chroma_format_idc: u32,
+    pub bit_depth_luma: u8,
+    pub bit_depth_chroma: u8,
+    pub frame_mbs_only: bool,
+
+    /// The displayed width, after frame cropping.
+    pub width: u32,
+
+    /// The displayed height, after frame cropping.
+    pub height: u32,
+
+    pub sample_aspect_ratio: Option<(u16, u16)>,
+    pub timing_info: Option<TimingInfo>,
+}
+
+/// VUI timing information.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct TimingInfo {
+    pub num_units_in_tick: u32,
+    pub time_scale: u32,
+    pub fixed_frame_rate: bool,
+}
+
+/// The leading fields of a picture parameter set.
+#[derive(Clone, Debug, Serialize)]
+pub struct Pps {
+    pub pic_parameter_set_id: u32,
+    pub seq_parameter_set_id: u32,
+
+    /// True for CABAC, false for CAVLC.
+    pub entropy_coding_mode: bool,
+}
+
+impl Parameters {
+    pub fn parse(format_specific_params: &BTreeMap<String, String>) -> Result<Self, String> {
+        let profile_level_id = format_specific_params
+            .get("profile-level-id")
+            .map(|p| ProfileLevelId::parse(p))
+            .transpose()?;
+        let packetization_mode = match format_specific_params.get("packetization-mode") {
+            None => 0,
+            Some(m) => match m.parse::<u8>() {
+                Ok(m @ 0..=2) => m,
+                _ => return Err(format!("bad packetization-mode {:?}", m)),
+            },
+        };
+        let mut params = Parameters {
+            profile_level_id,
+            packetization_mode,
+            sps: None,
+            pps: None,
+            sps_nal: None,
+            pps_nal: None,
+        };
+        let sprop_parameter_sets = match format_specific_params.get("sprop-parameter-sets") {
+            None => return Ok(params),
+            Some(s) => s,
+        };
+        for encoded in sprop_parameter_sets.split(',').filter(|e| !e.is_empty()) {
+            let nal = super::decode_base64(encoded)
+                .map_err(|e| format!("bad sprop-parameter-sets entry {:?}: {}", encoded, e))?;
+            let header = *nal
+                .first()
+                .ok_or_else(|| "empty NAL in sprop-parameter-sets".to_owned())?;
+            match header & 0x1f {
+                NAL_SPS => {
+                    params.sps = Some(Sps::parse(&nal).map_err(|e| format!("bad SPS: {}", e))?);
+                    params.sps_nal = Some(Bytes::from(nal));
+                }
+                NAL_PPS => {
+                    params.pps = Some(Pps::parse(&nal).map_err(|e| format!("bad PPS: {}", e))?);
+                    params.pps_nal = Some(Bytes::from(nal));
+                }
+                _ => {}
+            }
+        }
+        Ok(params)
+    }
+}
+
+impl ProfileLevelId {
+    pub fn parse(hex: &str) -> Result<Self, String> {
+        let bad = || format!("bad profile-level-id {:?}", hex);
+        if hex.len() != 6 {
+            return Err(bad());
+        }
+        let v = u32::from_str_radix(hex, 16).map_err(|_| bad())?;
+        Ok(Self {
+            profile_idc: (v >> 16) as u8,
+            constraint_flags: (v >> 8) as u8,
+            level_idc: v as u8,
+        })
+    }
+}
+
+impl Sps {
+    /// Parses a SPS NAL unit, including its one-byte header.
+    pub fn parse(nal: &[u8]) -> Result<Self, String> {
+        if nal.is_empty() || nal[0] & 0x1f != NAL_SPS {
+            return Err("not a SPS NAL unit".to_owned());
+        }
+        let rbsp = decode_rbsp(&nal[1..]);
+        let mut r = BitReader::new(&rbsp);
+        let profile_idc = r.read_u8(8)?;
+        let constraint_flags = r.read_u8(8)?;
+        let level_idc = r.read_u8(8)?;
+        let seq_parameter_set_id = r.read_ue()?;
+
+        let mut chroma_format_idc = 1;
+        let mut separate_colour_plane = false;
+        let mut bit_depth_luma = 8;
+        let mut bit_depth_chroma = 8;
+        if matches!(
+            profile_idc,
+            100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+        ) {
+            chroma_format_idc = r.read_ue()?;
+            if chroma_format_idc > 3 {
+                return Err(format!("bad chroma_format_idc {}", chroma_format_idc));
+            }
+            if chroma_format_idc == 3 {
+                separate_colour_plane = r.read_bit()?;
+            }
+            bit_depth_luma = bit_depth(r.read_ue()?)?;
+            bit_depth_chroma = bit_depth(r.read_ue()?)?;
+            r.read_bit()?; // qpprime_y_zero_transform_bypass_flag
+            if r.read_bit()? {
+                // seq_scaling_matrix_present_flag
+                let lists = if chroma_format_idc != 3 { 8 } else { 12 };
+                for i in 0..lists {
+                    if r.read_bit()? {
+                        skip_scaling_list(&mut r, if i < 6 { 16 } else { 64 })?;
+                    }
+                }
+            }
+        }
+
+        r.read_ue()?; // log2_max_frame_num_minus4
+        match r.read_ue()? {
+            0 => {
+                r.read_ue()?; // log2_max_pic_order_cnt_lsb_minus4
+            }
+            1 => {
+                r.read_bit()?; // delta_pic_order_always_zero_flag
+                r.read_se()?; // offset_for_non_ref_pic
+                r.read_se()?; // offset_for_top_to_bottom_field
+                let cycle = r.read_ue()?;
+                for _ in 0..cycle {
+                    r.read_se()?; // offset_for_ref_frame
+                }
+            }
+            2 => {}
+            t => return Err(format!("bad pic_order_cnt_type {}", t)),
+        }
+        r.read_ue()?; // max_num_ref_frames
+        r.read_bit()?; // gaps_in_frame_num_value_allowed_flag
+        let pic_width_in_mbs = r.read_ue()? + 1;
+        let pic_height_in_map_units = r.read_ue()? + 1;
+        let frame_mbs_only = r.read_bit()?;
+        if !frame_mbs_only {
+            r.read_bit()?; // mb_adaptive_frame_field_flag
+        }
+        r.read_bit()?; // direct_8x8_inference_flag
+
+        let (mut crop_left, mut crop_right, mut crop_top, mut crop_bottom) = (0, 0, 0, 0);
+        if r.read_bit()? {
+            crop_left = r.read_ue()?;
+            crop_right = r.read_ue()?;
+            crop_top = r.read_ue()?;
+            crop_bottom = r.read_ue()?;
+        }
+
+        // See the definitions of CropUnitX and CropUnitY in section 7.4.2.1.1.
+        let frame_height_factor = if frame_mbs_only { 1 } else { 2 };
+        let (crop_unit_x, crop_unit_y) = match (separate_colour_plane, chroma_format_idc) {
+            (true, _) | (_, 0) => (1, frame_height_factor),
+            (false, 1) => (2, 2 * frame_height_factor),
+            (false, 2) => (2, frame_height_factor),
+            (false, _) => (1, frame_height_factor),
+        };
+        let full_width = pic_width_in_mbs
+            .checked_mul(16)
+            .ok_or_else(|| format!("pic_width_in_mbs {} is too large", pic_width_in_mbs))?;
+        let full_height = pic_height_in_map_units
+            .checked_mul(16 * frame_height_factor)
+            .ok_or_else(|| {
+                format!(
+                    "pic_height_in_map_units {} is too large",
+                    pic_height_in_map_units
+                )
+            })?;
+        let width = crop_left
+            .checked_add(crop_right)
+            .and_then(|c| c.checked_mul(crop_unit_x))
+            .and_then(|c| full_width.checked_sub(c))
+            .ok_or_else(|| "horizontal cropping exceeds picture width".to_owned())?;
+        let height = crop_top
+            .checked_add(crop_bottom)
+            .and_then(|c| c.checked_mul(crop_unit_y))
+            .and_then(|c| full_height.checked_sub(c))
+            .ok_or_else(|| "vertical cropping exceeds picture height".to_owned())?;
+
+        let mut sample_aspect_ratio = None;
+        let mut timing_info = None;
+        if r.read_bit()? {
+            // vui_parameters_present_flag
+            if r.read_bit()? {
+                // aspect_ratio_info_present_flag
+                sample_aspect_ratio = match r.read_u8(8)? {
+                    255 => Some((r.read_bits(16)? as u16, r.read_bits(16)? as u16)),
+                    idc => aspect_ratio(idc),
+                };
+            }
+            if r.read_bit()? {
+                r.read_bit()?; // overscan_appropriate_flag
+            }
+            if r.read_bit()? {
+                // video_signal_type_present_flag
+                r.skip_bits(4)?; // video_format, video_full_range_flag
+                if r.read_bit()? {
+                    r.skip_bits(24)?; // colour_primaries, transfer, matrix
+                }
+            }
+            if r.read_bit()? {
+                r.read_ue()?; // chroma_sample_loc_type_top_field
+                r.read_ue()?; // chroma_sample_loc_type_bottom_field
+            }
+            if r.read_bit()? {
+                timing_info = Some(TimingInfo {
+                    num_units_in_tick: r.read_bits(32)?,
+                    time_scale: r.read_bits(32)?,
+                    fixed_frame_rate: r.read_bit()?,
+                });
+            }
+        }
+
+        Ok(Self {
+            profile_idc,
+            constraint_flags,
+            level_idc,
+            seq_parameter_set_id,
+            chroma_format_idc,
+            bit_depth_luma,
+            bit_depth_chroma,
+            frame_mbs_only,
+            width,
+            height,
+            sample_aspect_ratio,
+            timing_info,
+        })
+    }
+
+    /// Returns the frame rate from VUI timing information, if present.
+    pub fn frame_rate(&self) -> Option<f64> {
+        let t = self.timing_info?;
+        if t.num_units_in_tick == 0 || t.time_scale == 0 {
+            return None;
+        }
+
+        // Each frame is two fields' worth of ticks.
+        Some(f64::from(t.time_scale) / (2.0 * f64::from(t.num_units_in_tick)))
+    }
+
+    pub fn profile_name(&self) -> &'static str {
+        profile_name(self.profile_idc, self.constraint_flags)
+    }
+
+    /// Returns the level as written in Annex A, e.g. `3.1` or `1b`.
+    pub fn level(&self) -> String {
+        let constraint_set3 = self.constraint_flags & 0x10 != 0;
+        if self.level_idc == 9
+            || (self.level_idc == 11 && constraint_set3 && matches!(self.profile_idc, 66 | 77 | 88))
+        {
+            return "1b".to_owned();
+        }
+        format!("{}.{}", self.level_idc / 10, self.level_idc % 10)
+    }
+
+    pub fn chroma_format(&self) -> &'static str {
+        match self.chroma_format_idc {
+            0 => "4:0:0",
+            1 => "4:2:0",
+            2 => "4:2:2",
+            _ => "4:4:4",
+        }
+    }
+}
+
+impl Pps {
+    /// Parses a PPS NAL unit, including its one-byte header.
+    pub fn parse(nal: &[u8]) -> Result<Self, String> {
+        if nal.is_empty() || nal[0] & 0x1f != NAL_PPS {
+            return Err("not a PPS NAL unit".to_owned());
+        }
+        let rbsp = decode_rbsp(&nal[1..]);
+        let mut r = BitReader::new(&rbsp);
+        Ok(Self {
+            pic_parameter_set_id: r.read_ue()?,
+            seq_parameter_set_id: r.read_ue()?,
+            entropy_coding_mode: r.read_bit()?,
+        })
+    }
+}
+
+fn bit_depth(minus8: u32) -> Result<u8, String> {
+    if minus8 > 6 {
+        return Err(format!("bad bit depth {}", u64::from(minus8) + 8));
+    }
+    Ok(minus8 as u8 + 8)
+}
+
+/// Skips a `scaling_list()` as in section 7.3.2.1.1.1.
+fn skip_scaling_list(r: &mut BitReader, size: usize) -> Result<(), String> {
+    let mut last_scale: i64 = 8;
+    let mut next_scale: i64 = 8;
+    for _ in 0..size {
+        if next_scale != 0 {
+            let delta_scale = r.read_se()?;
+            if !(-128..=127).contains(&delta_scale) {
+                return Err(format!("delta_scale {} out of range", delta_scale));
+            }
+            next_scale = (last_scale + i64::from(delta_scale) + 256) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Ok(())
+}
+
+/// Table E-1, which H.265 shares.
+pub(crate) fn aspect_ratio(aspect_ratio_idc: u8) -> Option<(u16, u16)> {
+    Some(match aspect_ratio_idc {
+        1 => (1, 1),
+        2 => (12, 11),
+        3 => (10, 11),
+        4 => (16, 11),
+        5 => (40, 33),
+        6 => (24, 11),
+        7 => (20, 11),
+        8 => (32, 11),
+        9 => (80, 33),
+        10 => (18, 11),
+        11 => (15, 11),
+        12 => (64, 33),
+        13 => (160, 99),
+        14 => (4, 3),
+        15 => (3, 2),
+        16 => (2, 1),
+        _ => return None,
+    })
+}
+
+fn profile_name(profile_idc: u8, constraint_flags: u8) -> &'static str {
+    match profile_idc {
+        66 if constraint_flags & 0x40 != 0 => "Constrained Baseline",
+        66 => "Baseline",
+        77 => "Main",
+        88 => "Extended",
+        100 => "High",
+        110 => "High 10",
+        122 => "High 4:2:2",
+        244 => "High 4:4:4 Predictive",
+        44 => "CAVLC 4:4:4 Intra",
+        83 => "Scalable Baseline",
+        86 => "Scalable High",
+        118 => "Multiview High",
+        128 => "Stereo High",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::bits::testutil::{pack, ue};
+
+    /// From a 1080p camera's `sprop-parameter-sets`.
+    const SPS: &str = "Z00AKp2oHgCJ+WbgICAoAAADAAgAAAMBlCA=";
+    const PPS: &str = "aO48gA==";
+
+    #[test]
+    fn camera_parameter_sets() {
+        let fmtp = [
+            ("packetization-mode", "1"),
+            ("profile-level-id", "4d002a"),
+            ("sprop-parameter-sets", &format!("{},{}", SPS, PPS)[..]),
+        ]
+        .iter()
+        .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+        .collect();
+        let params = Parameters::parse(&fmtp).unwrap();
+        assert_eq!(params.packetization_mode, 1);
+        let plid = params.profile_level_id.unwrap();
+        assert_eq!(
+            (plid.profile_idc, plid.constraint_flags, plid.level_idc),
+            (77, 0, 42)
+        );
+
+        let sps = params.sps.unwrap();
+        assert_eq!((sps.width, sps.height), (1920, 1080));
+        assert_eq!(sps.profile_name(), "Main");
+        assert_eq!(sps.level(), "4.2");
+        assert_eq!(sps.chroma_format(), "4:2:0");
+        assert_eq!(sps.frame_rate(), Some(25.0));
+        assert_eq!(
+            params.sps_nal.as_deref(),
+            Some(&super::super::decode_base64(SPS).unwrap()[..])
+        );
+
+        let pps = params.pps.unwrap();
+        assert_eq!(pps.pic_parameter_set_id, 0);
+        assert_eq!(pps.seq_parameter_set_id, 0);
+        assert!(pps.entropy_coding_mode);
+    }
+
+    /// A Baseline SPS with the given picture size in macroblocks and
+    /// frame cropping.
+    fn baseline_sps(width_mbs_minus1: u32, crop: Option<[u32; 4]>) -> Vec<u8> {
+        let mut bits = String::new();
+        bits.push_str("01000010 00000000 00011110"); // profile 66, level 3.0
+        bits.push_str(&ue(0)); // seq_parameter_set_id
+        bits.push_str(&ue(0)); // log2_max_frame_num_minus4
+        bits.push_str(&ue(2)); // pic_order_cnt_type
+        bits.push_str(&ue(1)); // max_num_ref_frames
+        bits.push('0'); // gaps_in_frame_num_value_allowed_flag
+        bits.push_str(&ue(width_mbs_minus1));
+        bits.push_str(&ue(0)); // pic_height_in_map_units_minus1
+        bits.push_str("11"); // frame_mbs_only_flag, direct_8x8_inference_flag
+        match crop {
+            Some(c) => {
+                bits.push('1');
+                for v in c {
+                    bits.push_str(&ue(v));
+                }
+            }
+            None => bits.push('0'),
+        }
+        bits.push_str("0 1"); // vui_parameters_present_flag, rbsp_stop_one_bit
+        pack(&[0x67], &bits)
+    }
+
+    /// A 4:2:0 High profile SPS whose first scaling list starts with a
+    /// `delta_scale` of the given `ue(v)` code number.
+    fn high_sps(delta_scale_code: u32) -> Vec<u8> {
+        let mut bits = String::new();
+        bits.push_str("01100100 00000000 00011110"); // profile 100, level 3.0
+        bits.push_str(&ue(0)); // seq_parameter_set_id
+        bits.push_str(&ue(1)); // chroma_format_idc
+        bits.push_str(&ue(0)); // bit_depth_luma_minus8
+        bits.push_str(&ue(0)); // bit_depth_chroma_minus8
+        bits.push('0'); // qpprime_y_zero_transform_bypass_flag
+        bits.push('1'); // seq_scaling_matrix_present_flag
+        bits.push('1'); // seq_scaling_list_present_flag[0]
+        bits.push_str(&ue(delta_scale_code));
+        bits.push_str("0000000"); // seq_scaling_list_present_flag[1..8]
+        bits.push_str(&ue(0)); // log2_max_frame_num_minus4
+        bits.push_str(&ue(2)); // pic_order_cnt_type
+        bits.push_str(&ue(1)); // max_num_ref_frames
+        bits.push('0'); // gaps_in_frame_num_value_allowed_flag
+        bits.push_str(&ue(0)); // pic_width_in_mbs_minus1
+        bits.push_str(&ue(0)); // pic_height_in_map_units_minus1
+        bits.push_str("11"); // frame_mbs_only_flag, direct_8x8_inference_flag
+        bits.push_str("0 0 1"); // frame_cropping_flag, vui_parameters_present_flag, stop bit
+        pack(&[0x67], &bits)
+    }
+
+    #[test]
+    fn scaling_list_delta_scale() {
+        // A delta_scale of -8 makes the next scale 0, ending the list.
+        Sps::parse(&high_sps(16)).unwrap();
+
+        // 128 is just out of range; 2^32 - 3 codes 2^31 - 1, which overflowed.
+        let e = Sps::parse(&high_sps(255)).unwrap_err();
+        assert!(e.contains("delta_scale 128"), "{}", e);
+        let e = Sps::parse(&high_sps(u32::MAX - 2)).unwrap_err();
+        assert!(e.contains("delta_scale 2147483647"), "{}", e);
+    }
+
+    #[test]
+    fn cropping() {
+        let sps = Sps::parse(&baseline_sps(1, Some([1, 2, 0, 3]))).unwrap();
+        assert_eq!((sps.width, sps.height), (32 - 2 * 3, 16 - 2 * 3));
+        Sps::parse(&baseline_sps(0, Some([8, 1, 0, 0]))).unwrap_err();
+    }
+
+    #[test]
+    fn oversized_picture() {
+        let e = Sps::parse(&baseline_sps(1 << 30, None)).unwrap_err();
+        assert!(e.contains("too large"), "{}", e);
+
+        // The crop sum and product overflow.
+        let e = Sps::parse(&baseline_sps(0, Some([u32::MAX - 1, 2, 0, 0]))).unwrap_err();
+        assert!(e.contains("cropping"), "{}", e);
+        let e = Sps::parse(&baseline_sps(0, Some([0, 0, 1 << 31, 0]))).unwrap_err();
+        assert!(e.contains("cropping"), "{}", e);
+    }
+}
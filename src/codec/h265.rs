@@ -0,0 +1,617 @@
+//! H.265 parameters, as described in
+//! [RFC 7798 section 7.1](https://datatracker.ietf.org/doc/html/rfc7798#section-7.1).
+
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+use serde::Serialize;
+
+use super::bits::{decode_rbsp, BitReader};
+
+const NAL_VPS: u8 = 32;
+const NAL_SPS: u8 = 33;
+const NAL_PPS: u8 = 34;
+
+/// Parameters from a H.265 media section's `a=fmtp` line.
+#[derive(Clone, Debug, Serialize)]
+pub struct Parameters {
+    pub vps: Option<Vps>,
+    pub sps: Option<Sps>,
+    pub pps: Option<Pps>,
+
+    /// `sprop-max-don-diff`: if non-zero, payloads carry decoding order numbers.
+    pub max_don_diff: u16,
+
+    /// The raw NAL units from `sprop-vps`, `sprop-sps` and `sprop-pps`, headers included.
+    #[serde(skip)]
+    pub vps_nal: Option<Bytes>,
+    #[serde(skip)]
+    pub sps_nal: Option<Bytes>,
+    #[serde(skip)]
+    pub pps_nal: Option<Bytes>,
+}
+
+/// The general profile, tier and level, as in section 7.3.3.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct ProfileTierLevel {
+    pub profile_space: u8,
+
+    /// False for the Main tier, true for the High tier.
+    pub tier: bool,
+    pub profile_idc: u8,
+    pub profile_compatibility_flags: u32,
+    pub level_idc: u8,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Vps {
+    pub video_parameter_set_id: u8,
+    pub max_sub_layers: u8,
+    pub profile_tier_level: ProfileTierLevel,
+}
+
+/// The fields of a sequence parameter set that characterize the stream.
+#[derive(Clone, Debug, Serialize)]
+pub struct Sps {
+    pub video_parameter_set_id: u8,
+    pub max_sub_layers: u8,
+    pub profile_tier_level: ProfileTierLevel,
+    pub seq_parameter_set_id: u32,
+    pub chroma_format_idc: u32,
+    pub bit_depth_luma: u8,
+    pub bit_depth_chroma: u8,
+
+    /// The displayed width, after applying the conformance window.
+    pub width: u32,
+
+    /// The displayed height, after applying the conformance window.
+    pub height: u32,
+
+    pub sample_aspect_ratio: Option<(u16, u16)>,
+    pub timing_info: Option<TimingInfo>,
+}
+
+/// VUI timing information.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct TimingInfo {
+    pub num_units_in_tick: u32,
+    pub time_scale: u32,
+}
+
+/// The leading fields of a picture parameter set.
+#[derive(Clone, Debug, Serialize)]
+pub struct Pps {
+    pub pic_parameter_set_id: u32,
+    pub seq_parameter_set_id: u32,
+}
+
+impl Parameters {
+    pub fn parse(format_specific_params: &BTreeMap<String, String>) -> Result<Self, String> {
+        let mut params = Parameters {
+            vps: None,
+            sps: None,
+            pps: None,
+            max_don_diff: 0,
+            vps_nal: None,
+            sps_nal: None,
+            pps_nal: None,
+        };
+        if let Some(d) = format_specific_params.get("sprop-max-don-diff") {
+            params.max_don_diff = match d.parse::<u16>() {
+                Ok(d @ 0..=32767) => d,
+                _ => return Err(format!("bad sprop-max-don-diff {:?}", d)),
+            };
+        }
+        for (key, expected_type) in [
+            ("sprop-vps", NAL_VPS),
+            ("sprop-sps", NAL_SPS),
+            ("sprop-pps", NAL_PPS),
+        ] {
+            let value = match format_specific_params.get(key) {
+                None => continue,
+                Some(v) => v,
+            };
+
+            // Each parameter may hold several NAL units; the first describes the stream.
+            let encoded = match value.split(',').find(|e| !e.is_empty()) {
+                None => continue,
+                Some(e) => e,
+            };
+            let nal = super::decode_base64(encoded)
+                .map_err(|e| format!("bad {} {:?}: {}", key, encoded, e))?;
+            let nal_type = nal_type(&nal).ok_or_else(|| format!("{} is too short", key))?;
+            if nal_type != expected_type {
+                return Err(format!("{} holds NAL unit of type {}", key, nal_type));
+            }
+            let ctx = |e| format!("bad {}: {}", key, e);
+            match nal_type {
+                NAL_VPS => params.vps = Some(Vps::parse(&nal).map_err(ctx)?),
+                NAL_SPS => params.sps = Some(Sps::parse(&nal).map_err(ctx)?),
+                _ => params.pps = Some(Pps::parse(&nal).map_err(ctx)?),
+            }
+            let nal = Some(Bytes::from(nal));
+            match nal_type {
+                NAL_VPS => params.vps_nal = nal,
+                NAL_SPS => params.sps_nal = nal,
+                _ => params.pps_nal = nal,
+            }
+        }
+        Ok(params)
+    }
+}
+
+/// Returns the type from a NAL unit's two-byte header.
+pub(crate) fn nal_type(nal: &[u8]) -> Option<u8> {
+    if nal.len() < 2 {
+        return None;
+    }
+    Some((nal[0] >> 1) & 0x3f)
+}
+
+impl ProfileTierLevel {
+    /// Parses `profile_tier_level(1, max_sub_layers_minus1)`.
+    fn parse(r: &mut BitReader, max_sub_layers_minus1: u8) -> Result<Self, String> {
+        let profile_space = r.read_u8(2)?;
+        let tier = r.read_bit()?;
+        let profile_idc = r.read_u8(5)?;
+        let profile_compatibility_flags = r.read_bits(32)?;
+
+        // progressive, interlaced, non-packed and frame-only flags, then 43 reserved
+        // bits and general_inbld_flag.
+        r.skip_bits(48)?;
+        let level_idc = r.read_u8(8)?;
+
+        let mut sub_layer_profile_present = [false; 8];
+        let mut sub_layer_level_present = [false; 8];
+        for i in 0..usize::from(max_sub_layers_minus1) {
+            sub_layer_profile_present[i] = r.read_bit()?;
+            sub_layer_level_present[i] = r.read_bit()?;
+        }
+        if max_sub_layers_minus1 > 0 {
+            r.skip_bits(2 * (8 - usize::from(max_sub_layers_minus1)))?;
+        }
+        for i in 0..usize::from(max_sub_layers_minus1) {
+            if sub_layer_profile_present[i] {
+                r.skip_bits(88)?;
+            }
+            if sub_layer_level_present[i] {
+                r.skip_bits(8)?;
+            }
+        }
+        Ok(Self {
+            profile_space,
+            tier,
+            profile_idc,
+            profile_compatibility_flags,
+            level_idc,
+        })
+    }
+
+    pub fn profile_name(&self) -> &'static str {
+        // A profile_idc of 0 means the compatibility flags say what it is.
+        let idc = match self.profile_idc {
+            0 => (1u32..32)
+                .find(|&j| self.profile_compatibility_flags & (1 << (31 - j)) != 0)
+                .unwrap_or(0),
+            idc => u32::from(idc),
+        };
+        match idc {
+            1 => "Main",
+            2 => "Main 10",
+            3 => "Main Still Picture",
+            4 => "Format Range Extensions",
+            5 => "High Throughput",
+            9 => "Screen Content Coding",
+            _ => "Unknown",
+        }
+    }
+
+    pub fn tier_name(&self) -> &'static str {
+        if self.tier {
+            "High"
+        } else {
+            "Main"
+        }
+    }
+
+    /// Returns the level as written in Annex A, e.g. `4.1`.
+    pub fn level(&self) -> String {
+        // general_level_idc is 30 times the level number.
+        let tenths = self.level_idc / 3;
+        if tenths.is_multiple_of(10) {
+            format!("{}", tenths / 10)
+        } else {
+            format!("{}.{}", tenths / 10, tenths % 10)
+        }
+    }
+}
+
+impl Vps {
+    /// Parses a VPS NAL unit, including its two-byte header.
+    pub fn parse(nal: &[u8]) -> Result<Self, String> {
+        if nal_type(nal) != Some(NAL_VPS) {
+            return Err("not a VPS NAL unit".to_owned());
+        }
+        let rbsp = decode_rbsp(&nal[2..]);
+        let mut r = BitReader::new(&rbsp);
+        let video_parameter_set_id = r.read_u8(4)?;
+        r.skip_bits(2 + 6)?; // vps_base_layer_internal_flag, available_flag, max_layers_minus1
+        let max_sub_layers_minus1 = r.read_u8(3)?;
+        r.skip_bits(1 + 16)?; // vps_temporal_id_nesting_flag, vps_reserved_0xffff_16bits
+        let profile_tier_level = ProfileTierLevel::parse(&mut r, max_sub_layers_minus1)?;
+        Ok(Self {
+            video_parameter_set_id,
+            max_sub_layers: max_sub_layers_minus1 + 1,
+            profile_tier_level,
+        })
+    }
+}
+
+impl Sps {
+    /// Parses a SPS NAL unit, including its two-byte header.
+    pub fn parse(nal: &[u8]) -> Result<Self, String> {
+        if nal_type(nal) != Some(NAL_SPS) {
+            return Err("not a SPS NAL unit".to_owned());
+        }
+        let rbsp = decode_rbsp(&nal[2..]);
+        let mut r = BitReader::new(&rbsp);
+        let video_parameter_set_id = r.read_u8(4)?;
+        let max_sub_layers_minus1 = r.read_u8(3)?;
+        if max_sub_layers_minus1 > 6 {
+            return Err(format!(
+                "bad sps_max_sub_layers_minus1 {}",
+                max_sub_layers_minus1
+            ));
+        }
+        r.read_bit()?; // sps_temporal_id_nesting_flag
+        let profile_tier_level = ProfileTierLevel::parse(&mut r, max_sub_layers_minus1)?;
+        let seq_parameter_set_id = r.read_ue()?;
+        let chroma_format_idc = r.read_ue()?;
+        if chroma_format_idc > 3 {
+            return Err(format!("bad chroma_format_idc {}", chroma_format_idc));
+        }
+        let separate_colour_plane = chroma_format_idc == 3 && r.read_bit()?;
+        let pic_width = r.read_ue()?;
+        let pic_height = r.read_ue()?;
+        let (mut conf_left, mut conf_right, mut conf_top, mut conf_bottom) = (0, 0, 0, 0);
+        if r.read_bit()? {
+            // conformance_window_flag
+            conf_left = r.read_ue()?;
+            conf_right = r.read_ue()?;
+            conf_top = r.read_ue()?;
+            conf_bottom = r.read_ue()?;
+        }
+        let bit_depth_luma = bit_depth(r.read_ue()?)?;
+        let bit_depth_chroma = bit_depth(r.read_ue()?)?;
+        let log2_max_pic_order_cnt_lsb_minus4 = r.read_ue()?;
+        if log2_max_pic_order_cnt_lsb_minus4 > 12 {
+            return Err(format!(
+                "bad log2_max_pic_order_cnt_lsb_minus4 {}",
+                log2_max_pic_order_cnt_lsb_minus4
+            ));
+        }
+        let log2_max_pic_order_cnt_lsb = log2_max_pic_order_cnt_lsb_minus4 + 4;
+        let sub_layer_ordering_info_present = r.read_bit()?;
+        let first = if sub_layer_ordering_info_present {
+            0
+        } else {
+            max_sub_layers_minus1
+        };
+        for _ in first..=max_sub_layers_minus1 {
+            r.read_ue()?; // sps_max_dec_pic_buffering_minus1
+            r.read_ue()?; // sps_max_num_reorder_pics
+            r.read_ue()?; // sps_max_latency_increase_plus1
+        }
+        r.read_ue()?; // log2_min_luma_coding_block_size_minus3
+        r.read_ue()?; // log2_diff_max_min_luma_coding_block_size
+        r.read_ue()?; // log2_min_luma_transform_block_size_minus2
+        r.read_ue()?; // log2_diff_max_min_luma_transform_block_size
+        r.read_ue()?; // max_transform_hierarchy_depth_inter
+        r.read_ue()?; // max_transform_hierarchy_depth_intra
+        if r.read_bit()? && r.read_bit()? {
+            // scaling_list_enabled_flag && sps_scaling_list_data_present_flag
+            skip_scaling_list_data(&mut r)?;
+        }
+        r.read_bit()?; // amp_enabled_flag
+        r.read_bit()?; // sample_adaptive_offset_enabled_flag
+        if r.read_bit()? {
+            // pcm_enabled_flag
+            r.skip_bits(8)?; // pcm_sample_bit_depth_{luma,chroma}_minus1
+            r.read_ue()?; // log2_min_pcm_luma_coding_block_size_minus3
+            r.read_ue()?; // log2_diff_max_min_pcm_luma_coding_block_size
+            r.read_bit()?; // pcm_loop_filter_disabled_flag
+        }
+        let num_short_term_ref_pic_sets = r.read_ue()?;
+        if num_short_term_ref_pic_sets > 64 {
+            return Err(format!(
+                "bad num_short_term_ref_pic_sets {}",
+                num_short_term_ref_pic_sets
+            ));
+        }
+        let mut num_delta_pocs = Vec::with_capacity(num_short_term_ref_pic_sets as usize);
+        for idx in 0..num_short_term_ref_pic_sets as usize {
+            let n = skip_st_ref_pic_set(&mut r, idx, &num_delta_pocs)?;
+            num_delta_pocs.push(n);
+        }
+        if r.read_bit()? {
+            // long_term_ref_pics_present_flag
+            let num_long_term_ref_pics = r.read_ue()?;
+            for _ in 0..num_long_term_ref_pics {
+                r.skip_bits(log2_max_pic_order_cnt_lsb as usize + 1)?;
+            }
+        }
+        r.read_bit()?; // sps_temporal_mvp_enabled_flag
+        r.read_bit()?; // strong_intra_smoothing_enabled_flag
+
+        let mut sample_aspect_ratio = None;
+        let mut timing_info = None;
+        if r.read_bit()? {
+            // vui_parameters_present_flag
+            if r.read_bit()? {
+                // aspect_ratio_info_present_flag
+                sample_aspect_ratio = match r.read_u8(8)? {
+                    255 => Some((r.read_bits(16)? as u16, r.read_bits(16)? as u16)),
+                    idc => super::h264::aspect_ratio(idc),
+                };
+            }
+            if r.read_bit()? {
+                r.read_bit()?; // overscan_appropriate_flag
+            }
+            if r.read_bit()? {
+                // video_signal_type_present_flag
+                r.skip_bits(4)?; // video_format, video_full_range_flag
+                if r.read_bit()? {
+                    r.skip_bits(24)?; // colour_primaries, transfer, matrix
+                }
+            }
+            if r.read_bit()? {
+                r.read_ue()?; // chroma_sample_loc_type_top_field
+                r.read_ue()?; // chroma_sample_loc_type_bottom_field
+            }
+            r.skip_bits(3)?; // neutral_chroma_indication, field_seq, frame_field_info_present
+            if r.read_bit()? {
+                // default_display_window_flag
+                for _ in 0..4 {
+                    r.read_ue()?;
+                }
+            }
+            if r.read_bit()? {
+                timing_info = Some(TimingInfo {
+                    num_units_in_tick: r.read_bits(32)?,
+                    time_scale: r.read_bits(32)?,
+                });
+            }
+        }
+
+        // See the definitions of SubWidthC and SubHeightC in Table 6-1.
+        let (sub_width, sub_height) = match (separate_colour_plane, chroma_format_idc) {
+            (false, 1) => (2, 2),
+            (false, 2) => (2, 1),
+            _ => (1, 1),
+        };
+        let width = conf_left
+            .checked_add(conf_right)
+            .and_then(|c| c.checked_mul(sub_width))
+            .and_then(|c| pic_width.checked_sub(c))
+            .ok_or_else(|| "conformance window exceeds picture width".to_owned())?;
+        let height = conf_top
+            .checked_add(conf_bottom)
+            .and_then(|c| c.checked_mul(sub_height))
+            .and_then(|c| pic_height.checked_sub(c))
+            .ok_or_else(|| "conformance window exceeds picture height".to_owned())?;
+
+        Ok(Self {
+            video_parameter_set_id,
+            max_sub_layers: max_sub_layers_minus1 + 1,
+            profile_tier_level,
+            seq_parameter_set_id,
+            chroma_format_idc,
+            bit_depth_luma,
+            bit_depth_chroma,
+            width,
+            height,
+            sample_aspect_ratio,
+            timing_info,
+        })
+    }
+
+    /// Returns the frame rate from VUI timing information, if present.
+    pub fn frame_rate(&self) -> Option<f64> {
+        let t = self.timing_info?;
+        if t.num_units_in_tick == 0 || t.time_scale == 0 {
+            return None;
+        }
+        Some(f64::from(t.time_scale) / f64::from(t.num_units_in_tick))
+    }
+
+    pub fn chroma_format(&self) -> &'static str {
+        match self.chroma_format_idc {
+            0 => "4:0:0",
+            1 => "4:2:0",
+            2 => "4:2:2",
+            _ => "4:4:4",
+        }
+    }
+}
+
+impl Pps {
+    /// Parses a PPS NAL unit, including its two-byte header.
+    pub fn parse(nal: &[u8]) -> Result<Self, String> {
+        if nal_type(nal) != Some(NAL_PPS) {
+            return Err("not a PPS NAL unit".to_owned());
+        }
+        let rbsp = decode_rbsp(&nal[2..]);
+        let mut r = BitReader::new(&rbsp);
+        Ok(Self {
+            pic_parameter_set_id: r.read_ue()?,
+            seq_parameter_set_id: r.read_ue()?,
+        })
+    }
+}
+
+fn bit_depth(minus8: u32) -> Result<u8, String> {
+    if minus8 > 8 {
+        return Err(format!("bad bit depth {}", u64::from(minus8) + 8));
+    }
+    Ok(minus8 as u8 + 8)
+}
+
+/// Skips `scaling_list_data()` as in section 7.3.4.
+fn skip_scaling_list_data(r: &mut BitReader) -> Result<(), String> {
+    for size_id in 0..4 {
+        let step = if size_id == 3 { 3 } else { 1 };
+        for _ in (0..6).step_by(step) {
+            if !r.read_bit()? {
+                r.read_ue()?; // scaling_list_pred_matrix_id_delta
+                continue;
+            }
+            let coef_num = std::cmp::min(64, 1 << (4 + (size_id << 1)));
+            if size_id > 1 {
+                r.read_se()?; // scaling_list_dc_coef_minus8
+            }
+            for _ in 0..coef_num {
+                r.read_se()?; // scaling_list_delta_coef
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Skips `st_ref_pic_set(idx)` as in section 7.3.7, returning its `NumDeltaPocs`.
+fn skip_st_ref_pic_set(
+    r: &mut BitReader,
+    idx: usize,
+    num_delta_pocs: &[u32],
+) -> Result<u32, String> {
+    if idx != 0 && r.read_bit()? {
+        // inter_ref_pic_set_prediction_flag. delta_idx_minus1 is only present in
+        // slice headers, so the reference is always the previous set.
+        r.read_bit()?; // delta_rps_sign
+        r.read_ue()?; // abs_delta_rps_minus1
+        let mut n = 0;
+        for _ in 0..=num_delta_pocs[idx - 1] {
+            let used_by_curr_pic = r.read_bit()?;
+            let use_delta = used_by_curr_pic || r.read_bit()?;
+            if use_delta {
+                n += 1;
+            }
+        }
+        return Ok(n);
+    }
+    let num_negative_pics = r.read_ue()?;
+    let num_positive_pics = r.read_ue()?;
+    if num_negative_pics > 16 || num_positive_pics > 16 {
+        return Err(format!(
+            "bad short-term ref pic set with {} negative, {} positive pics",
+            num_negative_pics, num_positive_pics
+        ));
+    }
+    for _ in 0..num_negative_pics + num_positive_pics {
+        r.read_ue()?; // delta_poc_s{0,1}_minus1
+        r.read_bit()?; // used_by_curr_pic_s{0,1}_flag
+    }
+    Ok(num_negative_pics + num_positive_pics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::bits::testutil::{pack, ue};
+
+    /// From a 1080p camera's `sprop-vps`, `sprop-sps` and `sprop-pps`.
+    const VPS: &str = "QAEMAf//AWAAAAMAgAAAAwAAAwB4rAk=";
+    const SPS: &str = "QgEBAWAAAAMAgAAAAwAAAwB4oAPAgBDlja5JMvTcBAQEAg==";
+    const PPS: &str = "RAHA8vA8kAA=";
+
+    #[test]
+    fn camera_parameter_sets() {
+        let fmtp = [("sprop-vps", VPS), ("sprop-sps", SPS), ("sprop-pps", PPS)]
+            .iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect();
+        let params = Parameters::parse(&fmtp).unwrap();
+
+        let vps = params.vps.unwrap();
+        assert_eq!(vps.video_parameter_set_id, 0);
+        assert_eq!(vps.max_sub_layers, 1);
+        assert_eq!(vps.profile_tier_level.profile_name(), "Main");
+
+        let sps = params.sps.unwrap();
+        let ptl = &sps.profile_tier_level;
+        assert_eq!(ptl.profile_name(), "Main");
+        assert_eq!(ptl.tier_name(), "Main");
+        assert_eq!(ptl.level(), "4");
+        assert_eq!((sps.width, sps.height), (1920, 1080));
+        assert_eq!(sps.chroma_format(), "4:2:0");
+        assert_eq!((sps.bit_depth_luma, sps.bit_depth_chroma), (8, 8));
+
+        let pps = params.pps.unwrap();
+        assert_eq!(pps.pic_parameter_set_id, 0);
+        assert_eq!(pps.seq_parameter_set_id, 0);
+        assert_eq!(nal_type(params.pps_nal.as_deref().unwrap()), Some(NAL_PPS));
+    }
+
+    /// A Main profile 4:2:0 SPS with the given conformance window and
+    /// `log2_max_pic_order_cnt_lsb_minus4`.
+    fn main_sps(conf: [u32; 4], log2_max_pic_order_cnt_lsb_minus4: u32) -> Vec<u8> {
+        let mut bits = String::new();
+        bits.push_str("0000 000 1"); // vps id, max_sub_layers_minus1, nesting
+        bits.push_str("00 0 00001"); // profile_space, tier, profile_idc
+        bits.push_str(&format!("{:032b}", 0x6000_0000u32)); // compatibility flags
+        bits.push_str(&"0".repeat(48));
+        bits.push_str("01111000"); // level_idc
+        bits.push_str(&ue(0)); // sps_seq_parameter_set_id
+        bits.push_str(&ue(1)); // chroma_format_idc
+        bits.push_str(&ue(64)); // pic_width_in_luma_samples
+        bits.push_str(&ue(64)); // pic_height_in_luma_samples
+        bits.push('1'); // conformance_window_flag
+        for v in conf {
+            bits.push_str(&ue(v));
+        }
+        bits.push_str(&ue(0)); // bit_depth_luma_minus8
+        bits.push_str(&ue(0)); // bit_depth_chroma_minus8
+        bits.push_str(&ue(log2_max_pic_order_cnt_lsb_minus4));
+        bits.push('1'); // sps_sub_layer_ordering_info_present_flag
+        for _ in 0..9 {
+            bits.push_str(&ue(0));
+        }
+        bits.push_str("0000"); // scaling list, amp, sao, pcm
+        bits.push_str(&ue(0)); // num_short_term_ref_pic_sets
+        bits.push_str("0000"); // long-term refs, temporal mvp, smoothing, vui
+        bits.push('1'); // rbsp_stop_one_bit
+        pack(&[0x42, 0x01], &bits)
+    }
+
+    #[test]
+    fn conformance_window() {
+        let sps = Sps::parse(&main_sps([1, 2, 3, 4], 0)).unwrap();
+        assert_eq!((sps.width, sps.height), (64 - 2 * 3, 64 - 2 * 7));
+        Sps::parse(&main_sps([30, 3, 0, 0], 0)).unwrap_err();
+    }
+
+    #[test]
+    fn overflow() {
+        // The window's sum and product overflow.
+        let e = Sps::parse(&main_sps([u32::MAX - 1, 2, 0, 0], 0)).unwrap_err();
+        assert!(e.contains("conformance window"), "{}", e);
+        let e = Sps::parse(&main_sps([0, 0, 1 << 31, 0], 0)).unwrap_err();
+        assert!(e.contains("conformance window"), "{}", e);
+
+        let e = Sps::parse(&main_sps([0, 0, 0, 0], u32::MAX - 1)).unwrap_err();
+        assert!(e.contains("log2_max_pic_order_cnt_lsb_minus4"), "{}", e);
+    }
+
+    #[test]
+    fn max_don_diff() {
+        let parse = |d: &str| {
+            let fmtp = [("sprop-max-don-diff".to_owned(), d.to_owned())]
+                .into_iter()
+                .collect();
+            Parameters::parse(&fmtp).map(|p| p.max_don_diff)
+        };
+        assert_eq!(parse("0"), Ok(0));
+        assert_eq!(parse("32767"), Ok(32767));
+        parse("32768").unwrap_err();
+        parse("-1").unwrap_err();
+    }
+}
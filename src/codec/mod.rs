@@ -0,0 +1,102 @@
+//! Codec-specific parameters carried in SDP `a=fmtp` lines.
+
+use serde::Serialize;
+
+use crate::client::sdp::MediaStream;
+
+pub mod aac;
+mod bits;
+pub mod depacketize;
+pub mod h264;
+pub mod h265;
+
+/// Decoded parameters for a stream whose encoding is understood.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "codec", rename_all = "lowercase")]
+pub enum Parameters {
+    Aac(aac::Parameters),
+    H264(h264::Parameters),
+    H265(h265::Parameters),
+}
+
+impl Parameters {
+    /// Decodes `stream`'s format-specific parameters, returning `None` for
+    /// encodings this crate doesn't know about.
+    pub fn from_stream(stream: &MediaStream) -> Result<Option<Self>, String> {
+        let params = &stream.format_specific_params;
+        Ok(Some(match stream.encoding_name.to_ascii_lowercase().as_str() {
+            "h264" => Parameters::H264(h264::Parameters::parse(params)?),
+            "h265" => Parameters::H265(h265::Parameters::parse(params)?),
+            "mpeg4-generic" => Parameters::Aac(aac::Parameters::parse_generic(params)?),
+            "mp4a-latm" => Parameters::Aac(aac::Parameters::parse_latm(params)?),
+            _ => return Ok(None),
+        }))
+    }
+}
+
+/// A one-line human-readable summary, e.g. `H.264 High 4.1, 1920x1080 @ 25.000 fps`.
+impl std::fmt::Display for Parameters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Parameters::H264(p) => match &p.sps {
+                Some(sps) => {
+                    write!(
+                        f,
+                        "H.264 {} {}, {}x{}",
+                        sps.profile_name(),
+                        sps.level(),
+                        sps.width,
+                        sps.height
+                    )?;
+                    write_video_format(f, sps.frame_rate(), sps.chroma_format(), sps.bit_depth_luma)
+                }
+                None => f.write_str("H.264 without sprop-parameter-sets"),
+            },
+            Parameters::H265(p) => match &p.sps {
+                Some(sps) => {
+                    let ptl = &sps.profile_tier_level;
+                    write!(
+                        f,
+                        "H.265 {} {} tier {}, {}x{}",
+                        ptl.profile_name(),
+                        ptl.tier_name(),
+                        ptl.level(),
+                        sps.width,
+                        sps.height
+                    )?;
+                    write_video_format(f, sps.frame_rate(), sps.chroma_format(), sps.bit_depth_luma)
+                }
+                None => f.write_str("H.265 without sprop-sps"),
+            },
+            Parameters::Aac(p) => {
+                write!(
+                    f,
+                    "{}, {} Hz, ",
+                    p.config.object_type_name(),
+                    p.config.sampling_frequency
+                )?;
+                match p.config.channels() {
+                    Some(c) => write!(f, "{} channel(s)", c),
+                    None => f.write_str("channels in PCE"),
+                }
+            }
+        }
+    }
+}
+
+fn write_video_format(
+    f: &mut std::fmt::Formatter<'_>,
+    frame_rate: Option<f64>,
+    chroma_format: &str,
+    bit_depth: u8,
+) -> std::fmt::Result {
+    if let Some(fps) = frame_rate {
+        write!(f, " @ {:.3} fps", fps)?;
+    }
+    write!(f, ", {} {}-bit", chroma_format, bit_depth)
+}
+
+/// Decodes base64, tolerating the missing padding some cameras produce.
+fn decode_base64(encoded: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    base64::decode_config(encoded.trim().trim_end_matches('='), base64::STANDARD_NO_PAD)
+}
@@ -45,6 +45,19 @@ pub enum ErrorInt {
         description: String,
     },
 
+    #[error("[{conn_ctx}, {msg_ctx}] Unable to parse SDP: {description}")]
+    SdpParseError {
+        conn_ctx: ConnectionContext,
+        msg_ctx: RtspMessageContext,
+        description: String,
+    },
+
+    #[error("Invalid {encoding_name} parameters: {description}")]
+    CodecParametersError {
+        encoding_name: String,
+        description: String,
+    },
+
     #[error(
         "[{conn_ctx}, {msg_ctx}] Received interleaved data on unassigned channel {channel_id}"
     )]
@@ -54,6 +67,22 @@ pub enum ErrorInt {
         channel_id: u8,
     },
 
+    #[error("[{conn_ctx}, {msg_ctx}, channel={channel_id}] Bad RTP packet: {description}")]
+    RtpPacketError {
+        conn_ctx: ConnectionContext,
+        msg_ctx: RtspMessageContext,
+        channel_id: u8,
+        description: String,
+    },
+
+    #[error("[{conn_ctx}, {msg_ctx}, channel={channel_id}] Bad RTCP packet: {description}")]
+    RtcpPacketError {
+        conn_ctx: ConnectionContext,
+        msg_ctx: RtspMessageContext,
+        channel_id: u8,
+        description: String,
+    },
+
     #[error("Unable to connect to RTSP server: {0}")]
     ConnectError(#[source] std::io::Error),
 
@@ -76,6 +105,38 @@ pub enum ErrorInt {
     #[error("Internal error: {0}")]
     Internal(#[source] Box<dyn std::error::Error + Send + Sync>),
 
-    #[error("Timeout")]
-    Timeout,
+    #[error(
+        "{}{phase} timeout expired",
+        .conn_ctx.map(|c| format!("[{}] ", c)).unwrap_or_default()
+    )]
+    Timeout {
+        phase: TimeoutPhase,
+
+        /// The connection, if the timeout happened after it was established.
+        conn_ctx: Option<ConnectionContext>,
+    },
+}
+
+/// The stage of an operation that took too long; see
+/// [`crate::client::ConnectionOptions`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TimeoutPhase {
+    /// Establishing the TCP connection.
+    Connect,
+
+    /// Waiting for the response to a request.
+    Request,
+
+    /// The deadline for the operation as a whole.
+    Total,
+}
+
+impl Display for TimeoutPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TimeoutPhase::Connect => "connect",
+            TimeoutPhase::Request => "request",
+            TimeoutPhase::Total => "total",
+        })
+    }
 }
@@ -3,7 +3,9 @@ use std::net::{IpAddr, SocketAddr};
 use bytes::Bytes;
 use rtsp_types::Message;
 
+// Both macros deliberately name the calling crate's `error` module.
 #[macro_export]
+#[allow(clippy::crate_in_macro_def)]
 macro_rules! bail {
     ($e:expr) => {
         return Err(crate::error::Error(std::sync::Arc::new($e)))
@@ -11,6 +13,7 @@ macro_rules! bail {
 }
 
 #[macro_export]
+#[allow(clippy::crate_in_macro_def)]
 macro_rules! wrap {
     ($e:expr) => {
         crate::error::Error(std::sync::Arc::new($e))
@@ -0,0 +1,290 @@
+//! TLS for `rtsps://` URLs.
+
+use std::{sync::Arc, time::SystemTime};
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::{client::TlsStream, TlsConnector};
+use url::Host;
+
+/// How to verify the server's certificate.
+#[derive(Clone, Debug)]
+pub struct TlsOptions {
+    /// Trust the Mozilla root certificates bundled with `webpki-roots`.
+    pub use_builtin_roots: bool,
+
+    /// Additional DER-encoded root certificates to trust.
+    pub root_certificates: Vec<Vec<u8>>,
+
+    /// SHA-256 digests of acceptable server certificates. When non-empty, the
+    /// server's certificate must match one of these, and a match is trusted
+    /// without chain validation, as suits cameras with self-signed certificates.
+    pub pinned_sha256: Vec<[u8; 32]>,
+
+    /// Accept any certificate at all. This offers no protection against an
+    /// active attacker and is meant only for self-signed cameras on trusted
+    /// networks.
+    pub accept_invalid_certs: bool,
+}
+
+impl Default for TlsOptions {
+    fn default() -> Self {
+        Self {
+            use_builtin_roots: true,
+            root_certificates: Vec::new(),
+            pinned_sha256: Vec::new(),
+            accept_invalid_certs: false,
+        }
+    }
+}
+
+/// Reads all certificates from PEM data, returning them DER-encoded.
+pub fn parse_pem_certificates(mut pem: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let certs = rustls_pemfile::certs(&mut pem).map_err(|e| e.to_string())?;
+    if certs.is_empty() {
+        return Err("no certificates found".to_owned());
+    }
+    Ok(certs)
+}
+
+/// Parses a hex SHA-256 digest, optionally colon-separated as `openssl x509
+/// -fingerprint -sha256` prints it.
+pub fn parse_sha256_pin(hex: &str) -> Result<[u8; 32], String> {
+    let digits: String = hex.chars().filter(|&c| c != ':').collect();
+    let bad = || format!("bad SHA-256 digest {:?}", hex);
+    if digits.len() != 64 || !digits.is_ascii() {
+        return Err(bad());
+    }
+    let mut pin = [0u8; 32];
+    for (i, b) in pin.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).map_err(|_| bad())?;
+    }
+    Ok(pin)
+}
+
+pub(crate) fn client_config(options: &TlsOptions) -> Result<ClientConfig, String> {
+    let mut roots = RootCertStore::empty();
+    if options.use_builtin_roots {
+        roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
+    }
+    for der in &options.root_certificates {
+        roots
+            .add(&Certificate(der.clone()))
+            .map_err(|e| format!("bad root certificate: {}", e))?;
+    }
+    let verifier = Verifier {
+        webpki: WebPkiVerifier::new(roots, None),
+        pinned_sha256: options.pinned_sha256.clone(),
+        accept_invalid_certs: options.accept_invalid_certs,
+    };
+    Ok(ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth())
+}
+
+/// Performs a TLS handshake over `stream` with the server named by `host`.
+pub(crate) async fn connect<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    host: Host<&str>,
+    config: ClientConfig,
+) -> Result<TlsStream<S>, std::io::Error> {
+    let server_name = match host {
+        Host::Domain(d) => ServerName::try_from(d)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+        Host::Ipv4(a) => ServerName::IpAddress(a.into()),
+        Host::Ipv6(a) => ServerName::IpAddress(a.into()),
+    };
+    TlsConnector::from(Arc::new(config))
+        .connect(server_name, stream)
+        .await
+}
+
+struct Verifier {
+    webpki: WebPkiVerifier,
+    pinned_sha256: Vec<[u8; 32]>,
+    accept_invalid_certs: bool,
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if !self.pinned_sha256.is_empty() {
+            let digest = ring::digest::digest(&ring::digest::SHA256, &end_entity.0);
+            if self
+                .pinned_sha256
+                .iter()
+                .any(|pin| pin[..] == *digest.as_ref())
+            {
+                return Ok(ServerCertVerified::assertion());
+            }
+            return Err(rustls::Error::General(
+                "server certificate matches no pinned digest".to_owned(),
+            ));
+        }
+        if self.accept_invalid_certs {
+            return Ok(ServerCertVerified::assertion());
+        }
+        self.webpki.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+    use tokio_rustls::TlsAcceptor;
+
+    use super::*;
+
+    /// A CA, in DER and PEM, and a DER `localhost` certificate it signed.
+    struct Pki {
+        ca: Vec<u8>,
+        ca_pem: String,
+        leaf: Vec<u8>,
+        leaf_key: Vec<u8>,
+    }
+
+    fn pki() -> Pki {
+        let mut ca_params = rcgen::CertificateParams::new(Vec::new());
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+        let leaf = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        // Each serialization has a fresh signature, so encode the PEM from the DER.
+        let ca_der = ca.serialize_der().unwrap();
+        Pki {
+            ca_pem: format!(
+                "-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n",
+                base64::encode(&ca_der)
+            ),
+            ca: ca_der,
+            leaf: leaf.serialize_der_with_signer(&ca).unwrap(),
+            leaf_key: leaf.serialize_private_key_der(),
+        }
+    }
+
+    /// Serves one TLS connection on loopback with `pki`'s leaf certificate,
+    /// then connects to it with `options`, returning what the server sent.
+    async fn handshake(pki: &Pki, options: TlsOptions) -> Result<Vec<u8>, std::io::Error> {
+        let server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![Certificate(pki.leaf.clone())],
+                rustls::PrivateKey(pki.leaf_key.clone()),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            if let Ok(mut tls) = acceptor.accept(tcp).await {
+                let _ = tls.write_all(b"hello").await;
+                let _ = tls.shutdown().await;
+            }
+        });
+        let config = client_config(&options).unwrap();
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let result = async {
+            let mut tls = connect(tcp, Host::Domain("localhost"), config).await?;
+            let mut received = Vec::new();
+            tls.read_to_end(&mut received).await?;
+            Ok(received)
+        }
+        .await;
+        server.await.unwrap();
+        result
+    }
+
+    fn sha256(der: &[u8]) -> [u8; 32] {
+        let mut pin = [0; 32];
+        pin.copy_from_slice(ring::digest::digest(&ring::digest::SHA256, der).as_ref());
+        pin
+    }
+
+    #[tokio::test]
+    async fn untrusted_rejected() {
+        let pki = pki();
+        handshake(&pki, TlsOptions::default()).await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn custom_root() {
+        let pki = pki();
+        let options = TlsOptions {
+            use_builtin_roots: false,
+            root_certificates: parse_pem_certificates(pki.ca_pem.as_bytes()).unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(options.root_certificates, std::slice::from_ref(&pki.ca));
+        assert_eq!(handshake(&pki, options).await.unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn pinned() {
+        let pki = pki();
+        let pin = sha256(&pki.leaf);
+        let hex: String = pin
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(":");
+        assert_eq!(parse_sha256_pin(&hex).unwrap(), pin);
+        let options = TlsOptions {
+            pinned_sha256: vec![pin],
+            ..Default::default()
+        };
+        assert_eq!(handshake(&pki, options).await.unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn pin_mismatch_rejected() {
+        // A wrong pin fails even when the chain is trusted and invalid
+        // certificates are accepted.
+        let pki = pki();
+        let options = TlsOptions {
+            root_certificates: vec![pki.ca.clone()],
+            pinned_sha256: vec![sha256(&pki.ca)],
+            accept_invalid_certs: true,
+            ..Default::default()
+        };
+        handshake(&pki, options).await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn accept_invalid_certs() {
+        let pki = pki();
+        let options = TlsOptions {
+            use_builtin_roots: false,
+            accept_invalid_certs: true,
+            ..Default::default()
+        };
+        assert_eq!(handshake(&pki, options).await.unwrap(), b"hello");
+    }
+}
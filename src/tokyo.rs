@@ -1,11 +1,14 @@
-use std::time::Instant;
+use std::{net::SocketAddr, time::Instant};
 
 use bytes::{Bytes, BytesMut, Buf, BufMut};
 use futures::{Stream, Sink, SinkExt, StreamExt};
 use pretty_hex::PrettyHex;
 use rtsp_connection::{wrap, ConnectionContext, ReceivedMessage, RtspMessageContext, WallTime};
 use rtsp_types::{Data, Message};
-use tokio::net::TcpStream;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
 use tokio_util::codec::Framed;
 use url::Host;
 
@@ -148,23 +151,39 @@ impl tokio_util::codec::Encoder<rtsp_types::Message<Bytes>> for Codec {
     }
 }
 
-pub struct Connection(Framed<TcpStream, Codec>);
-impl Connection {
+/// A byte stream an RTSP connection can run over, such as TCP or TLS.
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+pub type BoxedTransport = Box<dyn Transport>;
+
+pub async fn connect_tcp(host: Host<&str>, port: u16) -> Result<TcpStream, std::io::Error> {
+    match host {
+        Host::Domain(h) => TcpStream::connect((h, port)).await,
+        Host::Ipv4(h) => TcpStream::connect((h, port)).await,
+        Host::Ipv6(h) => TcpStream::connect((h, port)).await,
+    }
+}
+
+pub struct Connection<S = BoxedTransport>(Framed<S, Codec>);
+
+impl Connection<TcpStream> {
     pub async fn connect(host: Host<&str>, port: u16) -> Result<Self, std::io::Error> {
-        let stream = match host {
-            Host::Domain(h) => TcpStream::connect((h, port)).await,
-            Host::Ipv4(h) => TcpStream::connect((h, port)).await,
-            Host::Ipv6(h) => TcpStream::connect((h, port)).await,
-        }?;
-        Self::from_stream(stream)
+        let stream = connect_tcp(host, port).await?;
+        let local_addr = stream.local_addr()?;
+        let peer_addr = stream.peer_addr()?;
+        Ok(Self::from_stream(stream, local_addr, peer_addr))
     }
+}
 
-    pub fn from_stream(stream: TcpStream) -> Result<Self, std::io::Error> {
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    /// Wraps an established stream. The addresses are those of the underlying
+    /// socket, for error context.
+    pub fn from_stream(stream: S, local_addr: SocketAddr, peer_addr: SocketAddr) -> Self {
         let established_wall = WallTime::now();
         let established = Instant::now();
-        let local_addr = stream.local_addr()?;
-        let peer_addr = stream.peer_addr()?;
-        Ok(Self(Framed::new(
+        Self(Framed::new(
             stream,
             Codec {
                 ctx: ConnectionContext {
@@ -175,7 +194,7 @@ impl Connection {
                 },
                 read_pos: 0,
             },
-        )))
+        ))
     }
 
     pub(crate) fn ctx(&self) -> &ConnectionContext {
@@ -202,7 +221,7 @@ impl Connection {
     }
 }
 
-impl Stream for Connection {
+impl<S: AsyncRead + AsyncWrite + Unpin> Stream for Connection<S> {
     type Item = Result<ReceivedMessage, Error>;
 
     fn poll_next(
@@ -230,7 +249,7 @@ impl Stream for Connection {
     }
 }
 
-impl Sink<Message<Bytes>> for Connection {
+impl<S: AsyncRead + AsyncWrite + Unpin> Sink<Message<Bytes>> for Connection<S> {
     type Error = ErrorInt;
 
     fn poll_ready(
@@ -0,0 +1,244 @@
+//! RTSP-over-HTTP tunneling, as introduced by QuickTime.
+//!
+//! The client opens two HTTP connections sharing an `x-sessioncookie`: a `GET`
+//! whose response body carries the server's RTSP messages, and a `POST` whose
+//! request body carries the client's RTSP messages, base64-encoded.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf},
+    net::TcpStream,
+};
+use url::Host;
+
+use crate::tokyo::connect_tcp;
+
+/// Both halves of an established tunnel, usable as a single byte stream.
+pub struct Tunnel {
+    get: BufReader<TcpStream>,
+    post: TcpStream,
+
+    /// Bytes written but not yet encoded.
+    pending: Vec<u8>,
+
+    /// Encoded bytes not yet written to `post`, starting at `encoded_pos`.
+    encoded: Vec<u8>,
+    encoded_pos: usize,
+}
+
+impl Tunnel {
+    /// Opens a tunnel to `host:port` at `path`, the HTTP request target
+    /// including any query string.
+    ///
+    /// Returns the tunnel along with the local and peer addresses of its `GET`
+    /// connection.
+    pub async fn connect(
+        host: Host<&str>,
+        port: u16,
+        path: &str,
+    ) -> Result<(Self, SocketAddr, SocketAddr), io::Error> {
+        let cookie = format!("{:016x}", RandomState::new().build_hasher().finish());
+        let host_header = match host {
+            Host::Ipv6(a) => format!("[{}]:{}", a, port),
+            ref h => format!("{}:{}", h, port),
+        };
+
+        let get = connect_tcp(host.clone(), port).await?;
+        let local_addr = get.local_addr()?;
+        let peer_addr = get.peer_addr()?;
+        let mut get = BufReader::new(get);
+        get.get_mut()
+            .write_all(
+                format!(
+                    "GET {} HTTP/1.0\r\n\
+                     Host: {}\r\n\
+                     x-sessioncookie: {}\r\n\
+                     Accept: application/x-rtsp-tunnelled\r\n\
+                     Pragma: no-cache\r\n\
+                     Cache-Control: no-cache\r\n\
+                     \r\n",
+                    path, host_header, cookie
+                )
+                .as_bytes(),
+            )
+            .await?;
+        read_response_head(&mut get).await?;
+
+        // The server matches the POST to the GET by cookie, so the GET must be
+        // established first. The POST gets no response until it's closed.
+        let mut post = connect_tcp(host, port).await?;
+        post.write_all(
+            format!(
+                "POST {} HTTP/1.0\r\n\
+                 Host: {}\r\n\
+                 x-sessioncookie: {}\r\n\
+                 Content-Type: application/x-rtsp-tunnelled\r\n\
+                 Pragma: no-cache\r\n\
+                 Cache-Control: no-cache\r\n\
+                 Content-Length: 32767\r\n\
+                 Expires: Sun, 9 Jan 1972 00:00:00 GMT\r\n\
+                 \r\n",
+                path, host_header, cookie
+            )
+            .as_bytes(),
+        )
+        .await?;
+        Ok((
+            Self {
+                get,
+                post,
+                pending: Vec::new(),
+                encoded: Vec::new(),
+                encoded_pos: 0,
+            },
+            local_addr,
+            peer_addr,
+        ))
+    }
+}
+
+/// Reads the status line and headers of the `GET` response, failing unless the
+/// status is 200.
+async fn read_response_head(get: &mut BufReader<TcpStream>) -> Result<(), io::Error> {
+    let mut status_line = String::new();
+    get.read_line(&mut status_line).await?;
+    let status_line = status_line.trim_end();
+    let status = status_line
+        .strip_prefix("HTTP/1.")
+        .and_then(|s| s.get(2..5))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad HTTP tunnel response {:?}", status_line),
+            )
+        })?;
+    if status != "200" {
+        return Err(io::Error::other(format!(
+            "HTTP tunnel GET failed: {}",
+            status_line
+        )));
+    }
+    loop {
+        let mut line = String::new();
+        if get.read_line(&mut line).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if line.trim_end().is_empty() {
+            return Ok(());
+        }
+    }
+}
+
+impl AsyncRead for Tunnel {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Tunnel {
+    /// Buffers `buf`; it's encoded and sent on the next flush.
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.pending.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if this.encoded_pos == this.encoded.len() {
+                if this.pending.is_empty() {
+                    break;
+                }
+                // Each flush is encoded as a self-contained, padded base64 chunk,
+                // which servers decode independently.
+                this.encoded = base64::encode(&this.pending).into_bytes();
+                this.encoded_pos = 0;
+                this.pending.clear();
+            }
+            let n = ready!(
+                Pin::new(&mut this.post).poll_write(cx, &this.encoded[this.encoded_pos..])
+            )?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            this.encoded_pos += n;
+        }
+        Pin::new(&mut this.post).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.post).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Reads an HTTP request head, returning its request line.
+    async fn read_request_head(conn: &mut BufReader<TcpStream>) -> String {
+        let mut request_line = String::new();
+        conn.read_line(&mut request_line).await.unwrap();
+        loop {
+            let mut line = String::new();
+            conn.read_line(&mut line).await.unwrap();
+            if line.trim_end().is_empty() {
+                return request_line.trim_end().to_owned();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn request_target_keeps_query() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let mut get = BufReader::new(listener.accept().await.unwrap().0);
+            let get_line = read_request_head(&mut get).await;
+            get.get_mut()
+                .write_all(b"HTTP/1.0 200 OK\r\n\r\n")
+                .await
+                .unwrap();
+            let mut post = BufReader::new(listener.accept().await.unwrap().0);
+            let post_line = read_request_head(&mut post).await;
+            let mut body = String::new();
+            post.read_to_string(&mut body).await.unwrap();
+            (get_line, post_line, body)
+        });
+        let (mut tunnel, _, _) = Tunnel::connect(
+            Host::Ipv4([127, 0, 0, 1].into()),
+            port,
+            "/live?channel=1&subtype=0",
+        )
+        .await
+        .unwrap();
+        tunnel.write_all(b"OPTIONS * RTSP/1.0\r\n").await.unwrap();
+        tunnel.shutdown().await.unwrap();
+        let (get_line, post_line, body) = server.await.unwrap();
+        assert_eq!(get_line, "GET /live?channel=1&subtype=0 HTTP/1.0");
+        assert_eq!(post_line, "POST /live?channel=1&subtype=0 HTTP/1.0");
+        assert_eq!(base64::decode(body).unwrap(), b"OPTIONS * RTSP/1.0\r\n");
+    }
+}